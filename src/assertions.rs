@@ -0,0 +1,172 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::connect::{format_duration, TimeUnit};
+use crate::statistics::{milliseconds, percentile, Statistics};
+
+/* What an `--assert` can be about */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Metric {
+    // out of 100, e.g. 99 for `p99`.
+    Percentile(f64),
+    Mean,
+    Median,
+    Fastest,
+    Slowest,
+    SuccessRate,
+    MeanSize,
+    LargestSize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparator {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparator {
+    fn holds(&self, left: f64, right: f64) -> bool {
+        return match self {
+            Comparator::Less => left < right,
+            Comparator::LessOrEqual => left <= right,
+            Comparator::Greater => left > right,
+            Comparator::GreaterOrEqual => left >= right,
+        };
+    }
+}
+
+/* One `--assert`, e.g. `p99<100ms` or `success_rate>=99.5` */
+#[derive(Debug, Clone)]
+pub struct Assertion {
+    raw: String,
+    metric: Metric,
+    comparator: Comparator,
+    // milliseconds for latencies, a percentage for the success rate, bytes for sizes.
+    threshold: f64,
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "{}", self.raw);
+    }
+}
+
+impl Assertion {
+    pub fn parse(raw: &str) -> Result<Assertion, String> {
+        let compact: String = raw.chars().filter(|c| !c.is_whitespace()).collect();
+        // the two-character comparators have to be looked for first.
+        let (position, comparator, width) = [
+            ("<=", Comparator::LessOrEqual), (">=", Comparator::GreaterOrEqual),
+            ("<", Comparator::Less), (">", Comparator::Greater),
+        ].iter()
+            .find_map(|(symbol, comparator)| compact.find(symbol).map(|position| (position, *comparator, symbol.len())))
+            .ok_or_else(|| format!("{:?} has no comparison in it - use one of <, <=, > or >=, e.g. p99<100ms", raw))?;
+        let (name, threshold) = (&compact[..position], &compact[position + width..]);
+
+        let metric = match name.to_ascii_lowercase().as_str() {
+            "mean" => Metric::Mean,
+            "median" => Metric::Median,
+            "min" | "fastest" => Metric::Fastest,
+            "max" | "slowest" => Metric::Slowest,
+            "success_rate" => Metric::SuccessRate,
+            "mean_size" => Metric::MeanSize,
+            "max_size" => Metric::LargestSize,
+            other => match other.strip_prefix('p').and_then(|p| p.parse::<f64>().ok()) {
+                Some(p) if p > 0.0 && p <= 100.0 => Metric::Percentile(p),
+                _ => return Err(format!(
+                    "{:?} isn't something to assert on - use pNN, mean, median, min, max, success_rate, mean_size or max_size", name
+                )),
+            },
+        };
+
+        let threshold = match metric {
+            Metric::SuccessRate => threshold.trim_end_matches('%').parse::<f64>().ok()
+                .filter(|percentage| (0.0..=100.0).contains(percentage))
+                .ok_or_else(|| format!("{:?} isn't a percentage between 0 and 100", threshold))?,
+            Metric::MeanSize | Metric::LargestSize => threshold.trim_end_matches('B').parse::<u64>().map(|bytes| bytes as f64)
+                .map_err(|_| format!("{:?} isn't a number of bytes", threshold))?,
+            _ => crate::parse_duration(threshold).map(milliseconds)
+                .ok_or_else(|| format!("{:?} isn't a duration, e.g. 100ms or 2s", threshold))?,
+        };
+
+        return Ok(Assertion{ raw: compact, metric, comparator, threshold });
+    }
+
+    /* The percentile it's about, for a `pNN` assertion */
+    pub fn percentile(&self) -> Option<f64> {
+        return match self.metric {
+            Metric::Percentile(p) => Some(p),
+            _ => None,
+        };
+    }
+
+    /* What the assertion is about, as observed in `statistics` - or None if
+       there was nothing to observe, e.g. no successful responses to time */
+    fn observe(&self, statistics: &Statistics) -> Option<f64> {
+        let latency = match self.metric {
+            Metric::SuccessRate => return Some(statistics.percentage_succeeded).filter(|rate| rate.is_finite()),
+            Metric::MeanSize => return statistics.mean_size,
+            Metric::LargestSize => return statistics.largest_size.map(|size| size as f64),
+            Metric::Percentile(p) if statistics.sorted_durations.is_empty() => statistics.estimated_percentiles.iter()
+                .find(|(estimated, _)| *estimated == p).map(|(_, estimate)| *estimate),
+            Metric::Percentile(p) => percentile(&statistics.sorted_durations, p),
+            Metric::Mean => statistics.mean,
+            Metric::Median => statistics.median,
+            Metric::Fastest => statistics.fastest,
+            Metric::Slowest => statistics.slowest,
+        };
+
+        return latency.map(milliseconds);
+    }
+
+    /* Checks the assertion, and describes how it went. With nothing to
+       observe, an assertion fails. */
+    pub fn check(&self, statistics: &Statistics, unit: Option<TimeUnit>) -> (bool, String) {
+        let observed = self.observe(statistics);
+        let passed = observed.is_some_and(|observed| self.comparator.holds(observed, self.threshold));
+        let described = match (observed, self.metric) {
+            (None, _) => String::from("nothing to measure"),
+            (Some(rate), Metric::SuccessRate) => format!("was {}%", rate),
+            (Some(size), Metric::MeanSize) | (Some(size), Metric::LargestSize) => format!("was {:.0} B", size),
+            (Some(latency), _) => format!("was {}", format_duration(unit, Duration::from_secs_f64(latency / 1000_f64))),
+        };
+        let report = format!("Assertion {} {} ({})", self, if passed { "passed" } else { "FAILED" }, described);
+
+        return (passed, report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProfilerConfig;
+    use crate::connect::Profiler;
+    use crate::serve::{serve_on, Reply};
+    use std::net::TcpListener;
+    use std::thread;
+    use url::Url;
+
+    #[test]
+    fn percentile_assertions_use_the_estimates_of_a_streaming_run() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let reply = Reply{ status: 200, body: String::from("ok"), delay: Duration::from_millis(0) };
+        thread::spawn(move || serve_on(listener, reply));
+
+        let mut profiler = Profiler::with_config(&target, ProfilerConfig::new(20).streaming_stats(true));
+        profiler.profile();
+        let statistics = profiler.statistics();
+        // nothing kept to work out exact percentiles from.
+        assert!(statistics.sorted_durations.is_empty());
+
+        for raw in ["p50<5s", "p95<5s", "p99<5s", "mean<5s"].iter() {
+            let (passed, report) = Assertion::parse(raw).unwrap().check(&statistics, None);
+            assert!(passed, "{}", report);
+        }
+        let (passed, report) = Assertion::parse("p95<0ms").unwrap().check(&statistics, None);
+        assert!(!passed);
+        assert!(!report.contains("nothing to measure"), "{}", report);
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+}
+
+impl Color {
+    fn code(&self) -> &'static str {
+        return match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+        };
+    }
+}
+
+// off until main decides otherwise, the same way the log level is a global:
+// escape codes are only any use on a terminal, and corrupt anything piped.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/* Wraps `text` in the escape codes for `color`, if colour is turned on */
+pub fn paint<T: Display>(text: T, color: Color) -> String {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return text.to_string();
+    }
+
+    return format!("\x1b[{}m{}\x1b[0m", color.code(), text);
+}
@@ -0,0 +1,501 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use url::Url;
+
+use crate::cookies::CookieJar;
+use crate::connect::{TimeUnit, DEFAULT_CONNECT_TIMEOUT, DEFAULT_MAX_RESPONSE_SIZE, DEFAULT_RETRIES};
+use crate::sigv4::SigV4;
+
+/* Everything that can be tuned about a profile, independent of what's being profiled.
+
+   Start from `ProfilerConfig::new` (or `default`, for a single request) and
+   chain setters for whatever needs to differ:
+
+       let config = ProfilerConfig::new(10).keep_alive(true).timeout(Some(Duration::from_secs(2)));
+       let mut profiler = Profiler::with_config(&target, config);
+
+   The same config can be cloned across any number of targets. */
+#[derive(Debug, Clone)]
+pub struct ProfilerConfig {
+    pub number_of_requests: i64,
+    // keep making requests for this long instead, however many that is.
+    pub run_for: Option<Duration>,
+    // how many requests to have in flight at once, each on its own connection.
+    pub concurrency: usize,
+    pub max_response_size: usize,
+    // stop starting requests once the responses so far add up to this many bytes.
+    pub max_total_bytes: Option<usize>,
+    // paths to spread requests across, each with its relative popularity.
+    pub weighted_paths: Vec<(String, f64)>,
+    // stop starting requests once this many have failed, across every worker.
+    pub abort_after_failures: Option<usize>,
+    // read responses this many bytes at a time, and report how fast they came in.
+    pub read_chunk_size: Option<usize>,
+    pub unit: Option<TimeUnit>,
+    pub keep_alive: bool,
+    // open this many connections before timing starts, and send over those first.
+    pub warm_pool: Option<usize>,
+    // wait a random time up to this long before each request, to spread them out.
+    pub jitter: Option<Duration>,
+    // where that randomness starts from; a fresh seed every run when not set.
+    pub seed: Option<u64>,
+    // wait this long before reusing a kept-alive connection, to see if the server keeps it open.
+    pub idle_probe: Option<Duration>,
+    pub compare_cache: bool,
+    // once a response carries an `ETag` or `Last-Modified`, make every request after it conditional.
+    pub conditional_requests: bool,
+    pub abort_on_tls_error: bool,
+    // a PEM file of CA certificates to trust besides the system's.
+    pub cacert: Option<String>,
+    pub preflight_origin: Option<String>,
+    pub connect_to: Option<String>,
+    // addresses to use for a (host, port) in place of asking DNS, like curl's `--resolve`.
+    pub resolve: BTreeMap<(String, u16), Vec<IpAddr>>,
+    // the nameserver to look the target up with, bypassing the system resolver.
+    pub dns_server: Option<SocketAddr>,
+    // the local address to send from, for hosts with more than one way out.
+    pub interface: Option<IpAddr>,
+    pub proxy: Option<Url>,
+    // `user:pass` for proxies that want Basic authentication.
+    pub proxy_user: Option<String>,
+    // dial only this address, rather than whatever the target resolves to.
+    pub pinned_address: Option<SocketAddr>,
+    pub timeout: Option<Duration>,
+    // how long each address gets to accept a connection before we give up on it.
+    pub connect_timeout: Duration,
+    // pace every connection to this many bytes a second each way, like a slow link.
+    pub throttle: Option<u64>,
+    pub handshake_only: bool,
+    pub report_deciles: bool,
+    // report what share of requests finished under each of these latencies.
+    pub latency_buckets: Vec<Duration>,
+    // the Apdex target time T, for scoring how satisfied users would be.
+    pub apdex_threshold: Option<Duration>,
+    pub report_total_time: bool,
+    // put a 95% confidence interval on the mean, for telling whether two runs really differ.
+    pub report_confidence: bool,
+    // fewer successful responses than this, and the statistics are flagged as unreliable.
+    pub min_samples: Option<usize>,
+    // report how many different bodies came back, to catch non-determinism.
+    pub hash_bodies: bool,
+    // count anything without an HTTP status line as a failure, rather than as status 0.
+    pub strict_parse: bool,
+    // count any response whose body isn't JSON as a failure.
+    pub validate_json_body: bool,
+    // send a request again when nothing at all comes back, up to `retries` times.
+    pub retry_empty: bool,
+    pub retries: u32,
+    // copy up to this many bytes of every raw response to stderr as it arrives.
+    pub tee: Option<usize>,
+    // responses slower than this still count towards latency, but are
+    // also reported as having missed the mark.
+    pub slow_threshold: Option<Duration>,
+    // keep the status line and headers in `document`, for protocol debugging.
+    pub include_headers_in_body: bool,
+    // stream an event per request to stdout instead of the end-of-run summary.
+    pub jsonl: bool,
+    // keep a live tally of the run on stdout while it goes.
+    pub dashboard: bool,
+    // estimate percentiles as responses come in, rather than keeping them
+    // all to work them out exactly at the end.
+    pub streaming_stats: bool,
+    // say which address the run's first connection went to.
+    pub show_resolution: bool,
+    pub body: Option<Vec<u8>>,
+    // the method to send in place of GET, or POST with a body - e.g. PURGE.
+    pub method: Option<String>,
+    // hold the body back until the server answers `100 Continue`.
+    pub expect_continue: bool,
+    // send HEAD rather than GET, for a quick look at status codes and headers.
+    pub head: bool,
+    // follow 3xx responses to wherever they lead, timing each hop.
+    pub follow_redirects: bool,
+    // when not empty, request `i` claims to be `user_agents[i % len]`.
+    pub user_agents: Vec<String>,
+    // the `Accept` to send; with more than one, requests take turns.
+    pub accepts: Vec<String>,
+    // headers every response must carry, optionally with a particular value.
+    pub expected_headers: Vec<(String, Option<String>)>,
+    // send a fresh ID in this header with every request, and note the slowest ones' IDs.
+    pub trace_header: Option<String>,
+    // sign every request for AWS with these credentials.
+    pub aws_sigv4: Option<SigV4>,
+    // what the first successful body must be, or the run is abandoned.
+    pub reference_body: Option<String>,
+    // the cookies to start out with; without a jar, `Set-Cookie` is ignored.
+    pub cookie_jar: Option<CookieJar>,
+}
+
+impl Default for ProfilerConfig {
+    fn default() -> ProfilerConfig {
+        return ProfilerConfig::new(1);
+    }
+}
+
+impl ProfilerConfig {
+    pub fn new(number_of_requests: i64) -> ProfilerConfig {
+        return ProfilerConfig{
+            number_of_requests,
+            run_for: None,
+            concurrency: 1,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            max_total_bytes: None,
+            weighted_paths: Vec::new(),
+            abort_after_failures: None,
+            read_chunk_size: None,
+            unit: None,
+            keep_alive: false,
+            warm_pool: None,
+            idle_probe: None,
+            jitter: None,
+            seed: None,
+            compare_cache: false,
+            conditional_requests: false,
+            abort_on_tls_error: false,
+            cacert: None,
+            preflight_origin: None,
+            connect_to: None,
+            resolve: BTreeMap::new(),
+            dns_server: None,
+            interface: None,
+            proxy: None,
+            proxy_user: None,
+            pinned_address: None,
+            timeout: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            throttle: None,
+            handshake_only: false,
+            report_deciles: false,
+            latency_buckets: Vec::new(),
+            apdex_threshold: None,
+            report_total_time: false,
+            report_confidence: false,
+            min_samples: None,
+            hash_bodies: false,
+            strict_parse: false,
+            validate_json_body: false,
+            retry_empty: false,
+            retries: DEFAULT_RETRIES,
+            tee: None,
+            slow_threshold: None,
+            include_headers_in_body: false,
+            jsonl: false,
+            dashboard: false,
+            streaming_stats: false,
+            show_resolution: false,
+            body: None,
+            method: None,
+            expect_continue: false,
+            head: false,
+            follow_redirects: false,
+            user_agents: Vec::new(),
+            accepts: Vec::new(),
+            expected_headers: Vec::new(),
+            trace_header: None,
+            aws_sigv4: None,
+            reference_body: None,
+            cookie_jar: None,
+        };
+    }
+
+    pub fn number_of_requests(mut self, number_of_requests: i64) -> ProfilerConfig {
+        self.number_of_requests = number_of_requests;
+        return self;
+    }
+
+    pub fn run_for(mut self, run_for: Option<Duration>) -> ProfilerConfig {
+        self.run_for = run_for;
+        return self;
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> ProfilerConfig {
+        self.concurrency = concurrency;
+        return self;
+    }
+
+    pub fn max_response_size(mut self, max_response_size: usize) -> ProfilerConfig {
+        self.max_response_size = max_response_size;
+        return self;
+    }
+
+    pub fn max_total_bytes(mut self, max_total_bytes: Option<usize>) -> ProfilerConfig {
+        self.max_total_bytes = max_total_bytes;
+        return self;
+    }
+
+    pub fn weighted_paths(mut self, weighted_paths: Vec<(String, f64)>) -> ProfilerConfig {
+        self.weighted_paths = weighted_paths;
+        return self;
+    }
+
+    pub fn abort_after_failures(mut self, abort_after_failures: Option<usize>) -> ProfilerConfig {
+        self.abort_after_failures = abort_after_failures;
+        return self;
+    }
+
+    pub fn read_chunk_size(mut self, read_chunk_size: Option<usize>) -> ProfilerConfig {
+        self.read_chunk_size = read_chunk_size;
+        return self;
+    }
+
+    pub fn unit(mut self, unit: Option<TimeUnit>) -> ProfilerConfig {
+        self.unit = unit;
+        return self;
+    }
+
+    pub fn keep_alive(mut self, keep_alive: bool) -> ProfilerConfig {
+        self.keep_alive = keep_alive;
+        return self;
+    }
+
+    pub fn warm_pool(mut self, warm_pool: Option<usize>) -> ProfilerConfig {
+        self.warm_pool = warm_pool;
+        return self;
+    }
+
+    pub fn jitter(mut self, jitter: Option<Duration>) -> ProfilerConfig {
+        self.jitter = jitter;
+        return self;
+    }
+
+    pub fn seed(mut self, seed: Option<u64>) -> ProfilerConfig {
+        self.seed = seed;
+        return self;
+    }
+
+    pub fn idle_probe(mut self, idle_probe: Option<Duration>) -> ProfilerConfig {
+        self.idle_probe = idle_probe;
+        return self;
+    }
+
+    pub fn compare_cache(mut self, compare_cache: bool) -> ProfilerConfig {
+        self.compare_cache = compare_cache;
+        return self;
+    }
+
+    pub fn conditional_requests(mut self, conditional_requests: bool) -> ProfilerConfig {
+        self.conditional_requests = conditional_requests;
+        return self;
+    }
+
+    pub fn abort_on_tls_error(mut self, abort_on_tls_error: bool) -> ProfilerConfig {
+        self.abort_on_tls_error = abort_on_tls_error;
+        return self;
+    }
+
+    pub fn cacert(mut self, cacert: Option<String>) -> ProfilerConfig {
+        self.cacert = cacert;
+        return self;
+    }
+
+    pub fn preflight_origin(mut self, preflight_origin: Option<String>) -> ProfilerConfig {
+        self.preflight_origin = preflight_origin;
+        return self;
+    }
+
+    pub fn connect_to(mut self, connect_to: Option<String>) -> ProfilerConfig {
+        self.connect_to = connect_to;
+        return self;
+    }
+
+    pub fn resolve(mut self, resolve: BTreeMap<(String, u16), Vec<IpAddr>>) -> ProfilerConfig {
+        self.resolve = resolve;
+        return self;
+    }
+
+    pub fn dns_server(mut self, dns_server: Option<SocketAddr>) -> ProfilerConfig {
+        self.dns_server = dns_server;
+        return self;
+    }
+
+    pub fn interface(mut self, interface: Option<IpAddr>) -> ProfilerConfig {
+        self.interface = interface;
+        return self;
+    }
+
+    pub fn proxy(mut self, proxy: Option<Url>) -> ProfilerConfig {
+        self.proxy = proxy;
+        return self;
+    }
+
+    pub fn proxy_user(mut self, proxy_user: Option<String>) -> ProfilerConfig {
+        self.proxy_user = proxy_user;
+        return self;
+    }
+
+    pub fn pinned_address(mut self, pinned_address: Option<SocketAddr>) -> ProfilerConfig {
+        self.pinned_address = pinned_address;
+        return self;
+    }
+
+    pub fn timeout(mut self, timeout: Option<Duration>) -> ProfilerConfig {
+        self.timeout = timeout;
+        return self;
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> ProfilerConfig {
+        self.connect_timeout = connect_timeout;
+        return self;
+    }
+
+    pub fn throttle(mut self, throttle: Option<u64>) -> ProfilerConfig {
+        self.throttle = throttle;
+        return self;
+    }
+
+    pub fn handshake_only(mut self, handshake_only: bool) -> ProfilerConfig {
+        self.handshake_only = handshake_only;
+        return self;
+    }
+
+    pub fn report_deciles(mut self, report_deciles: bool) -> ProfilerConfig {
+        self.report_deciles = report_deciles;
+        return self;
+    }
+
+    pub fn latency_buckets(mut self, latency_buckets: Vec<Duration>) -> ProfilerConfig {
+        self.latency_buckets = latency_buckets;
+        return self;
+    }
+
+    pub fn apdex_threshold(mut self, apdex_threshold: Option<Duration>) -> ProfilerConfig {
+        self.apdex_threshold = apdex_threshold;
+        return self;
+    }
+
+    pub fn report_total_time(mut self, report_total_time: bool) -> ProfilerConfig {
+        self.report_total_time = report_total_time;
+        return self;
+    }
+
+    pub fn report_confidence(mut self, report_confidence: bool) -> ProfilerConfig {
+        self.report_confidence = report_confidence;
+        return self;
+    }
+
+    pub fn min_samples(mut self, min_samples: Option<usize>) -> ProfilerConfig {
+        self.min_samples = min_samples;
+        return self;
+    }
+
+    pub fn hash_bodies(mut self, hash_bodies: bool) -> ProfilerConfig {
+        self.hash_bodies = hash_bodies;
+        return self;
+    }
+
+    pub fn strict_parse(mut self, strict_parse: bool) -> ProfilerConfig {
+        self.strict_parse = strict_parse;
+        return self;
+    }
+
+    pub fn validate_json_body(mut self, validate_json_body: bool) -> ProfilerConfig {
+        self.validate_json_body = validate_json_body;
+        return self;
+    }
+
+    pub fn retry_empty(mut self, retry_empty: bool) -> ProfilerConfig {
+        self.retry_empty = retry_empty;
+        return self;
+    }
+
+    pub fn retries(mut self, retries: u32) -> ProfilerConfig {
+        self.retries = retries;
+        return self;
+    }
+
+    pub fn tee(mut self, tee: Option<usize>) -> ProfilerConfig {
+        self.tee = tee;
+        return self;
+    }
+
+    pub fn slow_threshold(mut self, slow_threshold: Option<Duration>) -> ProfilerConfig {
+        self.slow_threshold = slow_threshold;
+        return self;
+    }
+
+    pub fn include_headers_in_body(mut self, include_headers_in_body: bool) -> ProfilerConfig {
+        self.include_headers_in_body = include_headers_in_body;
+        return self;
+    }
+
+    pub fn jsonl(mut self, jsonl: bool) -> ProfilerConfig {
+        self.jsonl = jsonl;
+        return self;
+    }
+
+    pub fn dashboard(mut self, dashboard: bool) -> ProfilerConfig {
+        self.dashboard = dashboard;
+        return self;
+    }
+
+    pub fn streaming_stats(mut self, streaming_stats: bool) -> ProfilerConfig {
+        self.streaming_stats = streaming_stats;
+        return self;
+    }
+
+    pub fn show_resolution(mut self, show_resolution: bool) -> ProfilerConfig {
+        self.show_resolution = show_resolution;
+        return self;
+    }
+
+    pub fn body(mut self, body: Option<Vec<u8>>) -> ProfilerConfig {
+        self.body = body;
+        return self;
+    }
+
+    pub fn method(mut self, method: Option<String>) -> ProfilerConfig {
+        self.method = method;
+        return self;
+    }
+
+    pub fn expect_continue(mut self, expect_continue: bool) -> ProfilerConfig {
+        self.expect_continue = expect_continue;
+        return self;
+    }
+
+    pub fn head(mut self, head: bool) -> ProfilerConfig {
+        self.head = head;
+        return self;
+    }
+
+    pub fn follow_redirects(mut self, follow_redirects: bool) -> ProfilerConfig {
+        self.follow_redirects = follow_redirects;
+        return self;
+    }
+
+    pub fn user_agents(mut self, user_agents: Vec<String>) -> ProfilerConfig {
+        self.user_agents = user_agents;
+        return self;
+    }
+
+    pub fn accepts(mut self, accepts: Vec<String>) -> ProfilerConfig {
+        self.accepts = accepts;
+        return self;
+    }
+
+    pub fn expected_headers(mut self, expected_headers: Vec<(String, Option<String>)>) -> ProfilerConfig {
+        self.expected_headers = expected_headers;
+        return self;
+    }
+
+    pub fn trace_header(mut self, trace_header: Option<String>) -> ProfilerConfig {
+        self.trace_header = trace_header;
+        return self;
+    }
+
+    pub fn aws_sigv4(mut self, aws_sigv4: Option<SigV4>) -> ProfilerConfig {
+        self.aws_sigv4 = aws_sigv4;
+        return self;
+    }
+
+    pub fn reference_body(mut self, reference_body: Option<String>) -> ProfilerConfig {
+        self.reference_body = reference_body;
+        return self;
+    }
+
+    pub fn cookie_jar(mut self, cookie_jar: Option<CookieJar>) -> ProfilerConfig {
+        self.cookie_jar = cookie_jar;
+        return self;
+    }
+}
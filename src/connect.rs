@@ -1,241 +1,3346 @@
 use regex::Regex;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::error::Error;
+use std::cell::Cell;
 use std::fmt;
-use std::net::TcpStream;
+use std::mem;
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
 use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use openssl::error::ErrorStack;
 use openssl::ssl::{SslMethod, SslConnector, SslStream};
-use std::io::{Read, Write};
+use std::io::{self, ErrorKind, Read, Write};
 use itertools::Itertools;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use url::Url;
 
+use crate::color::{paint, Color};
+use crate::config::ProfilerConfig;
+use crate::cookies::CookieJar;
+use crate::dashboard::Dashboard;
+use crate::dns;
+use crate::json::{self, Json};
+use crate::quantiles::{StreamingStats, STREAMING_QUANTILES};
+use crate::random::{self, Xorshift};
+use crate::socket::connect_from;
+use crate::statistics::{mean, median, milliseconds, percentile, size_weighted_mean, Statistics};
+use crate::throttle::Throttled;
+
+/* Every address we tried, and why each of them failed - or none, if the
+   target resolved to nothing at all */
+#[derive(Debug, Clone)]
+pub struct NotReachableError {
+    pub attempts: Vec<(SocketAddr, String)>,
+}
+
+impl fmt::Display for NotReachableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.attempts.is_empty() {
+            return write!(f, "Could not connect to URL: no addresses to try");
+        }
+
+        let reasons = self.attempts.iter().map(|(address, reason)| format!("{}: {}", address, reason)).join("; ");
+        return write!(f, "Could not connect to URL: no host was reachable (tried {})", reasons);
+    }
+}
+
+impl Error for NotReachableError {
+    fn description(&self) -> &str {
+        return "Could not connect to URL: no host was reachable";
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResponseTooLargeError {
+    pub limit: usize,
+}
+
+impl fmt::Display for ResponseTooLargeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Aborted read: response exceeded the maximum size of {} B", self.limit);
+    }
+}
+
+impl Error for ResponseTooLargeError {
+    fn description(&self) -> &str {
+        return "Aborted read: response exceeded the maximum size";
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestTimeoutError;
+
+impl fmt::Display for RequestTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Request did not complete within its overall --timeout");
+    }
+}
+
+impl Error for RequestTimeoutError {
+    fn description(&self) -> &str {
+        return "Request did not complete within its overall --timeout";
+    }
+}
+
+/* A TLS handshake that failed for a single request, as opposed to bad TLS setup */
+#[derive(Debug, Clone)]
+pub struct TlsHandshakeError {
+    pub message: String,
+}
+
+impl fmt::Display for TlsHandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "TLS handshake failed: {}", self.message);
+    }
+}
+
+impl Error for TlsHandshakeError {
+    fn description(&self) -> &str {
+        return "TLS handshake failed";
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TooManyRedirectsError {
+    pub limit: usize,
+}
+
+impl fmt::Display for TooManyRedirectsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Gave up following redirects after {} of them", self.limit);
+    }
+}
+
+impl Error for TooManyRedirectsError {
+    fn description(&self) -> &str {
+        return "Gave up following redirects";
+    }
+}
+
+/* A redirect led back to somewhere the chain had already been */
+#[derive(Debug, Clone)]
+pub struct RedirectLoopError {
+    pub url: String,
+    pub hops: usize,
+}
+
+impl fmt::Display for RedirectLoopError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Redirect loop detected: the chain came back to {} after {} redirect{}", self.url, self.hops, if self.hops == 1 { "" } else { "s" });
+    }
+}
+
+impl Error for RedirectLoopError {
+    fn description(&self) -> &str {
+        return "Redirect loop detected";
+    }
+}
+
+/* With `--strict-parse`, what came back wasn't an HTTP response at all */
+#[derive(Debug, Clone)]
+pub struct MalformedResponseError {
+    // the first line of it, for telling what it was instead.
+    pub first_line: String,
+}
+
+impl fmt::Display for MalformedResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Response was not HTTP - it began {:?}", self.first_line);
+    }
+}
+
+impl Error for MalformedResponseError {
+    fn description(&self) -> &str {
+        return "Response was not HTTP";
+    }
+}
+
+/* With `--validate-json-body`, a response's body didn't parse as JSON */
+#[derive(Debug, Clone)]
+pub struct InvalidJsonBodyError {
+    // where and how it stopped being JSON.
+    pub reason: String,
+    // the start of the body, for telling what it was instead.
+    pub first_line: String,
+}
+
+impl fmt::Display for InvalidJsonBodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Response body was not valid JSON ({}) - it began {:?}", self.reason, self.first_line);
+    }
+}
+
+impl Error for InvalidJsonBodyError {
+    fn description(&self) -> &str {
+        return "Response body was not valid JSON";
+    }
+}
+
+/* With `--retry-empty`, nothing at all came back however often we asked */
+#[derive(Debug, Clone)]
+pub struct EmptyResponseError {
+    pub attempts: u32,
+}
+
+impl fmt::Display for EmptyResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Empty response: the server sent nothing back, {} times running", self.attempts);
+    }
+}
+
+impl Error for EmptyResponseError {
+    fn description(&self) -> &str {
+        return "Empty response: the server sent nothing back";
+    }
+}
+
+/* The proxy refused to open a tunnel to the target for us */
+#[derive(Debug, Clone)]
+pub struct ProxyTunnelError {
+    pub status_line: String,
+}
+
+impl fmt::Display for ProxyTunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Proxy refused to CONNECT: {}", self.status_line);
+    }
+}
+
+impl Error for ProxyTunnelError {
+    fn description(&self) -> &str {
+        return "Proxy refused to CONNECT";
+    }
+}
+
+/* The proxy wanted credentials, and either we had none or it turned them down */
+#[derive(Debug, Clone)]
+pub struct ProxyAuthError {
+    pub status_line: String,
+}
+
+impl fmt::Display for ProxyAuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        return write!(f, "Proxy authentication failed: {}", self.status_line);
+    }
+}
+
+impl Error for ProxyAuthError {
+    fn description(&self) -> &str {
+        return "Proxy authentication failed";
+    }
+}
+
+/* The broad reason a request failed, for telling a flaky network from a broken server */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureCategory {
+    Dns,
+    Connect,
+    Tls,
+    Timeout,
+    Io,
+    // a response came back, but not one we could make sense of or take in.
+    Parse,
+    // the proxy, not the origin, turned us away for want of credentials.
+    ProxyAuth,
+}
+
+impl FailureCategory {
+    pub const ALL: [FailureCategory; 7] = [
+        FailureCategory::Dns, FailureCategory::Connect, FailureCategory::Tls,
+        FailureCategory::Timeout, FailureCategory::Io, FailureCategory::Parse,
+        FailureCategory::ProxyAuth,
+    ];
+
+    pub fn of(failure: &(dyn Error + Send + Sync + 'static)) -> FailureCategory {
+        if failure.is::<RequestTimeoutError>() {
+            return FailureCategory::Timeout;
+        }
+        if failure.is::<ProxyAuthError>() {
+            return FailureCategory::ProxyAuth;
+        }
+        if failure.is::<TlsHandshakeError>() || failure.is::<openssl::ssl::Error>() || failure.is::<openssl::error::ErrorStack>() {
+            return FailureCategory::Tls;
+        }
+        if failure.is::<NotReachableError>() || failure.is::<ProxyTunnelError>() {
+            return FailureCategory::Connect;
+        }
+        if failure.is::<ResponseTooLargeError>() || failure.is::<MalformedResponseError>()
+            || failure.is::<InvalidJsonBodyError>() || failure.is::<EmptyResponseError>() {
+            return FailureCategory::Parse;
+        }
+
+        return match failure.downcast_ref::<io::Error>() {
+            Some(e) if is_timeout(e) => FailureCategory::Timeout,
+            Some(e) if e.kind() == ErrorKind::ConnectionRefused || e.kind() == ErrorKind::AddrNotAvailable => FailureCategory::Connect,
+            Some(e) if e.kind() == ErrorKind::InvalidData => FailureCategory::Parse,
+            // std doesn't give lookup failures a kind of their own, only this wording.
+            Some(e) if e.to_string().contains("lookup") => FailureCategory::Dns,
+            Some(_) => FailureCategory::Io,
+            None => FailureCategory::Parse,
+        };
+    }
+
+    pub fn label(&self) -> &'static str {
+        return match self {
+            FailureCategory::Dns => "dns",
+            FailureCategory::Connect => "connect",
+            FailureCategory::Tls => "tls",
+            FailureCategory::Timeout => "timeout",
+            FailureCategory::Io => "io",
+            FailureCategory::Parse => "parse",
+            FailureCategory::ProxyAuth => "proxy_auth",
+        };
+    }
+}
+
+// how long any single read or write on a socket may block for.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(3);
+
+// how long to hold a body back waiting on `100 Continue` before sending it
+// anyway, as servers that don't understand `Expect` never answer. Same as curl.
+const EXPECT_CONTINUE_WAIT: Duration = Duration::from_secs(1);
+
+// how many of the slowest requests `--trace-header` lists the IDs of.
+const SLOWEST_TRACES: usize = 5;
+
+// how long each connection attempt gets a head start on the next, when an
+// address has more than one - RFC 8305's recommended "Connection Attempt Delay".
+const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+// how many redirects `--follow-redirects` goes through before giving up on a chain.
+const MAX_REDIRECTS: usize = 10;
+
+/* What `Profiler::set_response_hook` keeps, shareable between workers */
+#[derive(Clone)]
+pub struct ResponseHook(Arc<dyn Fn(&ResponseProperties) + Send + Sync>);
+
+// there's nothing to show of a closure, but Profiler is Debug.
+impl fmt::Debug for ResponseHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "ResponseHook");
+    }
+}
+
+/* Connections whose read timeout can be tightened as a request's deadline nears */
+pub trait ReadDeadline {
+    fn set_read_wait(&self, wait: Duration) -> io::Result<()>;
+}
+
+impl ReadDeadline for TcpStream {
+    fn set_read_wait(&self, wait: Duration) -> io::Result<()> {
+        return self.set_read_timeout(Some(wait));
+    }
+}
+
+impl ReadDeadline for SslStream<TcpStream> {
+    fn set_read_wait(&self, wait: Duration) -> io::Result<()> {
+        return self.get_ref().set_read_timeout(Some(wait));
+    }
+}
+
+/* Connections that can tell the server we've nothing more to send, for
+   servers that wait for that before they answer */
+pub trait HalfClose {
+    fn shutdown_write(&self) -> io::Result<()>;
+}
+
+impl HalfClose for TcpStream {
+    fn shutdown_write(&self) -> io::Result<()> {
+        return self.shutdown(Shutdown::Write);
+    }
+}
+
+// a TLS close_notify ends the whole session, not just our half, so TLS
+// connections stay open both ways.
+impl HalfClose for SslStream<TcpStream> {
+    fn shutdown_write(&self) -> io::Result<()> {
+        return Ok(());
+    }
+}
+
+/* Connections that can say which address they ended up connected to */
+pub trait PeerAddress {
+    fn peer_address(&self) -> io::Result<SocketAddr>;
+}
+
+impl PeerAddress for TcpStream {
+    fn peer_address(&self) -> io::Result<SocketAddr> {
+        return self.peer_addr();
+    }
+}
+
+impl PeerAddress for SslStream<TcpStream> {
+    fn peer_address(&self) -> io::Result<SocketAddr> {
+        return self.get_ref().peer_addr();
+    }
+}
+
+// large enough for any sane page, small enough that a server streaming
+// garbage at us can't exhaust memory.
+pub const DEFAULT_MAX_RESPONSE_SIZE: usize = 64 * 1024 * 1024;
+
+// how much we ask for in each read of a response, unless told otherwise.
+pub const DEFAULT_READ_CHUNK_SIZE: usize = 8192;
+
+// the usual lines drawn in SLAs, for `--buckets` without any of its own.
+pub const DEFAULT_LATENCY_BUCKETS: [Duration; 6] = [
+    Duration::from_millis(10), Duration::from_millis(50), Duration::from_millis(100),
+    Duration::from_millis(250), Duration::from_millis(500), Duration::from_secs(1),
+];
+
+// how many times `--retry-empty` asks again, unless told otherwise.
+pub const DEFAULT_RETRIES: u32 = 3;
+
+// how long we give each address to accept a connection, unless told otherwise.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// what we've always claimed to be, and to take.
+const DEFAULT_USER_AGENT: &str = "curl/7.58.0";
+const DEFAULT_ACCEPT: &str = "*/*";
+
+// what `--rotate-user-agent` cycles through without a file of its own: a few
+// browsers, a crawler and a command-line client, since those are the
+// distinctions servers tend to draw.
+pub const BUILT_IN_USER_AGENTS: [&str; 5] = [
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/86.0.4240.75 Safari/537.36",
+    "Mozilla/5.0 (X11; Linux x86_64; rv:81.0) Gecko/20100101 Firefox/81.0",
+    "Mozilla/5.0 (iPhone; CPU iPhone OS 14_0 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/14.0 Mobile/15E148 Safari/604.1",
+    "Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)",
+    DEFAULT_USER_AGENT,
+];
+
+/* Unit that reported durations are rendered in, for `--unit` */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimeUnit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+impl TimeUnit {
+    pub fn from_flag(flag: &str) -> Option<TimeUnit> {
+        return match flag {
+            "s" => Some(TimeUnit::Seconds),
+            "ms" => Some(TimeUnit::Milliseconds),
+            "us" => Some(TimeUnit::Microseconds),
+            _ => None,
+        };
+    }
+
+    /* Renders `duration` in this unit with a fixed number of decimal places */
+    pub fn format(&self, duration: Duration) -> String {
+        let seconds = duration.as_secs_f64();
+        return match self {
+            TimeUnit::Seconds => format!("{:.3}s", seconds),
+            TimeUnit::Milliseconds => format!("{:.3}ms", seconds * 1e3),
+            TimeUnit::Microseconds => format!("{:.3}us", seconds * 1e6),
+        };
+    }
+}
+
+/* Renders a duration in the unit picked with `--unit`, if any */
+pub fn format_duration(unit: Option<TimeUnit>, duration: Duration) -> String {
+    return match unit {
+        Some(unit) => unit.format(duration),
+        None => format!("{:?}", duration),
+    };
+}
+
+/* The broad class a response's status code falls into */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum StatusClass {
+    Success,
+    Redirect,
+    ClientError,
+    ServerError,
+    // 1xx, or a status line we couldn't make sense of.
+    Other,
+}
+
+impl StatusClass {
+    pub fn from_status(status_code: i32) -> StatusClass {
+        return match status_code {
+            200..=299 => StatusClass::Success,
+            300..=399 => StatusClass::Redirect,
+            400..=499 => StatusClass::ClientError,
+            500..=599 => StatusClass::ServerError,
+            _ => StatusClass::Other,
+        };
+    }
+
+    pub fn label(&self) -> &'static str {
+        return match self {
+            StatusClass::Success => "success",
+            StatusClass::Redirect => "redirect",
+            StatusClass::ClientError => "client_error",
+            StatusClass::ServerError => "server_error",
+            StatusClass::Other => "other",
+        };
+    }
+}
+
+/* One request along a redirect chain */
+#[derive(Debug, Clone)]
+pub struct HopTiming {
+    pub url: String,
+    pub status_code: i32,
+    pub time_taken: Duration,
+}
+
 #[derive(Debug, Clone)]
-pub struct NotReachableError;
+pub struct ResponseProperties {
+    pub time_taken: Duration,
+    // from just before we started connecting (if we had to) until the body
+    // was read - i.e. the whole wait a client would see.
+    pub total_time: Duration,
+    // when that wait began, for ordering responses across workers.
+    pub started_at: Option<Instant>,
+    pub status_code: i32,
+    pub classification: StatusClass,
+    // just the body, unless we were asked to keep the whole raw response.
+    pub document: String,
+    // the body's size either way.
+    pub body_size: usize,
+    // a fingerprint of the body as it came off the wire, for telling
+    // identical responses apart without holding on to them.
+    pub body_hash: u64,
+    // the status line and headers, up to and including the blank line.
+    pub header_size: usize,
+    // trailers included, after the headers proper.
+    pub headers: Vec<(String, String)>,
+    // whatever followed the last chunk of a chunked body, e.g. gRPC's `grpc-status`.
+    pub trailers: Vec<(String, String)>,
+    // what the server told us about reusing the connection this arrived on.
+    pub keep_alive_max: Option<u64>,
+    pub closes_connection: bool,
+    // what Content-Length promised, where it applies, against the raw bytes
+    // that actually followed the headers.
+    pub declared_length: Option<usize>,
+    pub received_length: usize,
+    // only known when we were rotating through user agents.
+    pub user_agent: Option<String>,
+    // likewise, for rotating through `Accept` values.
+    pub accept: Option<String>,
+    // and for `--path-weights`, the path as it was written in the file.
+    pub path: Option<String>,
+    // the address of whoever answered - the proxy, if there was one.
+    pub server_address: Option<SocketAddr>,
+    // the server reset the connection partway through, so this is only
+    // what arrived before it did.
+    pub incomplete: bool,
+    // from the request's headers going out to `100 Continue` coming back,
+    // when we sent `Expect: 100-continue` and the server obliged.
+    pub continue_wait: Option<Duration>,
+    // how long the proxy took to open the tunnel this response came through,
+    // on the first response over each tunnel only.
+    pub tunnel_time: Option<Duration>,
+    // how long `--dns-server` took to answer, on the first response over each connection.
+    pub dns_time: Option<Duration>,
+    // with `--follow-redirects`, every request it took to get here, this
+    // one last - or empty if there was no redirect to follow.
+    pub redirect_chain: Vec<HopTiming>,
+    // with `--trace-header`, the ID to look this request up by in a tracing system.
+    pub trace_id: Option<String>,
+    // as the status line gave it, e.g. `HTTP/1.1` - None if there wasn't one.
+    pub http_version: Option<String>,
+    // bytes a second from the end of the first read to the end of the last,
+    // so the wait for the first byte doesn't count - None for a single read.
+    pub transfer_rate: Option<f64>,
+    // the longest wait between two reads of the response.
+    pub longest_stall: Option<Duration>,
+    // from the same start as `time_taken` until the blank line ending the
+    // headers came in, however long the body then took.
+    pub time_to_headers: Option<Duration>,
+    // body bytes a second while writing the request, for requests with one.
+    pub upload_rate: Option<f64>,
+}
+
+impl ResponseProperties {
+    /* Case-insensitively looks up the first value of a response header */
+    pub fn header(&self, name: &str) -> Option<&str> {
+        return self.headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str());
+    }
+
+    /* Whether the server closed the connection without sending a single byte */
+    pub fn is_empty(&self) -> bool {
+        return self.status_code == 0 && self.header_size == 0 && self.received_length == 0;
+    }
+
+    /* Whether the body was cut short of, or ran past, its Content-Length */
+    pub fn length_mismatch(&self) -> bool {
+        return self.declared_length.is_some_and(|declared| declared != self.received_length);
+    }
+
+    /* The media type of the response, without any `; charset=...` parameters */
+    pub fn content_type(&self) -> Option<String> {
+        return self.header("Content-Type")
+            .map(|value| value.split(';').next().unwrap().trim().to_ascii_lowercase());
+    }
+}
+
+#[derive(Debug)]
+pub struct Profiler<'a> {
+    pub target: &'a Url,
+    pub config: ProfilerConfig,
+    // starts out as the configured jar, and picks up `Set-Cookie`s as we go.
+    pub cookie_jar: Option<CookieJar>,
+    formatted_request: Vec<u8>,
+    preflight_request: Vec<u8>,
+    // the agent and `Accept` that `formatted_request` currently carries, when rotating.
+    user_agent: Option<String>,
+    accept: Option<String>,
+    // likewise, the trace ID sent in `--trace-header`.
+    trace_id: Option<String>,
+    // with `--path-weights`, the path drawn for the current request and the
+    // URL it makes against the target.
+    path: Option<(String, Url)>,
+    // for `--repeat-with-cache-headers`, the `If-None-Match` and
+    // `If-Modified-Since` to send, once a response has given us something for them.
+    validators: Option<Vec<(String, String)>>,
+    // the one source of randomness for the whole run, e.g. `--jitter`'s
+    // delays, and the seed it started from - so a run can be replayed.
+    pub seed: u64,
+    rng: Xorshift,
+    // with their `document`s emptied out - only the longest is ever shown,
+    // so it's the only one worth the memory.
+    pub successful_responses: Vec<ResponseProperties>,
+    // the longest body so far, as (body size, document).
+    pub longest_document: Option<(usize, String)>,
+    // whether the first successful response has been checked against `--reference-body`.
+    reference_checked: bool,
+    pub failed_responses: Vec<Box<dyn Error + Send + Sync>>,
+    pub handshake_times: Vec<Duration>,
+    // when each attempt completed, and whether it succeeded.
+    pub outcomes: Vec<(Instant, bool)>,
+    // counts completions across every worker, to number `--jsonl` events.
+    completed: Arc<AtomicUsize>,
+    // every byte of every successful response so far, across every worker -
+    // for `--max-total-bytes`.
+    bytes_read: Arc<AtomicUsize>,
+    // every failure so far, across every worker - for `--abort-after-failures`.
+    failures_so_far: Arc<AtomicUsize>,
+    // for `--show-resolution`, whether any worker has connected yet.
+    connected_once: Arc<AtomicBool>,
+    // shared by every worker, like `completed`, when there's a `--dashboard`.
+    dashboard: Option<Arc<Dashboard>>,
+    // for `--streaming-stats`, in place of `successful_responses` - and
+    // shared by every worker, like the dashboard.
+    streaming: Option<Arc<Mutex<StreamingStats>>>,
+    // called with every successful response, for whatever's embedding us.
+    response_hook: Option<ResponseHook>,
+    // from the start of `profile` to the end, for throughput.
+    pub wall_time: Duration,
+    pub preflight_responses: Vec<ResponseProperties>,
+    pub failed_preflights: Vec<Box<dyn Error + Send + Sync>>,
+    pub reconnections: u64,
+    pub reconnection_time: Duration,
+    // those of `reconnections` we made because a request failed on the
+    // connection before, rather than the server closing it between requests.
+    pub error_reconnections: u64,
+    // how many `--warm-pool` connections were opened, and how long that took.
+    pub warm_connections: usize,
+    pub warm_pool_time: Duration,
+    // for `--idle-probe`, how many idle connections the server kept and closed.
+    pub idle_survivals: u64,
+    pub idle_closures: u64,
+    // for `--retry-empty`, how many times a request went again for getting nothing back.
+    pub empty_retries: u64,
+    // left by `open_tunnel` for the request on the new connection to pick up,
+    // as connecting only gets to borrow us.
+    tunnel_time: Cell<Option<Duration>>,
+    // likewise, left by `resolve` when it asked `--dns-server`.
+    dns_time: Cell<Option<Duration>>,
+    // how many connections IPv6 and IPv4 won, of those where both were in the running.
+    family_wins: Cell<(u64, u64)>,
+}
+
+impl Profiler<'_> {
+
+    pub fn with_config(target: &Url, config: ProfilerConfig) -> Profiler<'_> {
+        let seed = config.seed.unwrap_or_else(random::fresh_seed);
+        let dashboard = if config.dashboard { Some(Arc::new(Dashboard::new(config.unit))) } else { None };
+        let streaming = if config.streaming_stats { Some(Arc::new(Mutex::new(StreamingStats::new()))) } else { None };
+        return Profiler{
+            target,
+            cookie_jar: config.cookie_jar.clone(),
+            config,
+            formatted_request: Vec::new(),
+            preflight_request: Vec::new(),
+            user_agent: None,
+            accept: None,
+            path: None,
+            trace_id: None,
+            validators: None,
+            seed,
+            rng: Xorshift::new(seed),
+            successful_responses: Vec::new(),
+            longest_document: None,
+            reference_checked: false,
+            failed_responses: Vec::new(),
+            handshake_times: Vec::new(),
+            outcomes: Vec::new(),
+            completed: Arc::new(AtomicUsize::new(0)),
+            bytes_read: Arc::new(AtomicUsize::new(0)),
+            failures_so_far: Arc::new(AtomicUsize::new(0)),
+            connected_once: Arc::new(AtomicBool::new(false)),
+            dashboard,
+            streaming,
+            response_hook: None,
+            wall_time: Duration::new(0, 0),
+            preflight_responses: Vec::new(),
+            failed_preflights: Vec::new(),
+            reconnections: 0,
+            reconnection_time: Duration::new(0, 0),
+            error_reconnections: 0,
+            warm_connections: 0,
+            warm_pool_time: Duration::new(0, 0),
+            idle_survivals: 0,
+            idle_closures: 0,
+            empty_retries: 0,
+            tunnel_time: Cell::new(None),
+            dns_time: Cell::new(None),
+            family_wins: Cell::new((0, 0)),
+        }
+    }
+
+    /* Sends `content` and reads back the response. `head_request` says it was a
+       HEAD request, whose response stops at the headers whatever they claim */
+    fn fetch<T: Read + Write + ReadDeadline + HalfClose>(&self, connection: &mut T, content: &[u8], head_request: bool) -> Result<ResponseProperties, Box<dyn Error + Send + Sync>> {
+        let deadline = self.config.timeout.map(|timeout| Instant::now() + timeout);
+
+        // with `Expect: 100-continue`, the body waits until the server says
+        // it wants it - so the timing below includes that extra round trip.
+        let header_end = find_subsequence(content, b"\r\n\r\n");
+        let body_length = header_end.map_or(0, |end| content.len() - end - 4);
+        let (head, body) = match header_end {
+            Some(end) if self.config.expect_continue && end + 4 < content.len() => content.split_at(end + 4),
+            _ => (content, &content[content.len()..]),
+        };
+        // only the writes, not any wait for `100 Continue` between them.
+        let mut write_time = timed_write(connection, head)?;
+
+        let before = Instant::now();
+        let mut read_buffer = Vec::new();
+        let mut continue_wait = None;
+        let mut body_withheld = false;
+        if !body.is_empty() {
+            match read_interim(connection, EXPECT_CONTINUE_WAIT)? {
+                Some(interim) if parse_status_code_and_page(&interim).0 == 100 => {
+                    continue_wait = Some(Instant::now().duration_since(before));
+                    write_time += timed_write(connection, body)?;
+                }
+                // the server answered without wanting the body - a 417, say.
+                Some(response_head) => {
+                    read_buffer = response_head;
+                    body_withheld = true;
+                }
+                None => {
+                    debug!("No 100 Continue within {:?}, sending the body anyway", EXPECT_CONTINUE_WAIT);
+                    write_time += timed_write(connection, body)?;
+                }
+            }
+        }
+        // a flush says nothing to the server, but a half-close does: without
+        // keep-alive, the request is all it's getting.
+        if !self.config.keep_alive {
+            if let Err(e) = connection.shutdown_write() {
+                debug!("Could not shut down our half of the connection: {}", e);
+            }
+        }
+        let chunk_size = self.config.read_chunk_size.unwrap_or(DEFAULT_READ_CHUNK_SIZE);
+        let (read_buffer, incomplete, arrivals) = read_response(
+            connection, read_buffer, self.config.max_response_size, chunk_size, self.config.keep_alive, head_request, deadline
+        )?;
+        let elapsed_time = Instant::now().duration_since(before);
+        if let Some(limit) = self.config.tee {
+            self.tee(&read_buffer, limit);
+        }
+
+        let mut statistic = parse_response(&read_buffer, elapsed_time, head_request);
+        // an empty response is for `--retry-empty` to deal with, if it's on.
+        if self.config.strict_parse && statistic.status_code == 0 && !(self.config.retry_empty && statistic.is_empty()) {
+            let first_line = String::from_utf8_lossy(&read_buffer).lines().next().unwrap_or("").chars().take(80).collect();
+            return Err(Box::new(MalformedResponseError{ first_line }));
+        }
+        // a 407 over a plain proxied request is the proxy's, not the origin's.
+        if self.config.proxy.is_some() && self.target.scheme() != "https" && statistic.status_code == 407 {
+            let status_line = String::from_utf8_lossy(&read_buffer).lines().next().unwrap_or("").to_string();
+            return Err(Box::new(ProxyAuthError{ status_line }));
+        }
+        // a 204 or 304 has no body to be JSON, whatever the rest say.
+        if self.config.validate_json_body && !head_request && statistic.status_code != 204 && statistic.status_code != 304 {
+            if let Err(reason) = json::validate(&statistic.document) {
+                let first_line = statistic.document.lines().next().unwrap_or("").chars().take(80).collect();
+                return Err(Box::new(InvalidJsonBodyError{ reason, first_line }));
+            }
+        }
+        statistic.continue_wait = continue_wait;
+        if body_length > 0 && !body_withheld && write_time > Duration::from_nanos(0) {
+            statistic.upload_rate = Some(body_length as f64 / write_time.as_secs_f64());
+        }
+        statistic.transfer_rate = arrivals.transfer_rate();
+        statistic.longest_stall = arrivals.longest_gap;
+        statistic.time_to_headers = arrivals.headers_complete.map(|complete| complete.duration_since(before));
+        // there's no telling whether the server's still expecting that body.
+        if body_withheld {
+            statistic.closes_connection = true;
+        }
+        if incomplete {
+            // whatever the headers said, there's no connection left to reuse.
+            statistic.incomplete = true;
+            statistic.closes_connection = true;
+        }
+        if self.config.include_headers_in_body {
+            statistic.document = String::from_utf8_lossy(&read_buffer).to_string();
+        }
+        return Ok(statistic);
+    }
+
+    /* For `--tee`: copies a raw response to stderr, in one write so that
+       concurrent workers' responses don't end up interleaved */
+    fn tee(&self, response: &[u8], limit: usize) {
+        let shown = &response[..response.len().min(limit)];
+        let mut copy = format!("--- {} B from {}", response.len(), self.target).into_bytes();
+        if shown.len() < response.len() {
+            copy.extend_from_slice(format!(", the first {} B", shown.len()).as_bytes());
+        }
+        copy.extend_from_slice(b" ---\n");
+        copy.extend_from_slice(shown);
+        if !copy.ends_with(b"\n") {
+            copy.push(b'\n');
+        }
+        // stderr going away is no reason to stop profiling.
+        let _ = io::stderr().lock().write_all(&copy);
+    }
+
+    /* Looks up every address we could dial for this profile, in the order the resolver gave them */
+    pub fn resolve(&self) -> Result<Vec<SocketAddr>, Box<dyn Error + Send + Sync>> {
+        // `--connect-to` only changes where we dial - the request itself still
+        // names the URL's host.
+        let socket_addresses = match (&self.config.proxy, &self.config.connect_to) {
+            (Some(proxy), _) => {
+                info!("Dialing proxy {}", proxy);
+                proxy.socket_addrs(|| None)?
+            }
+            (None, Some(address)) => {
+                info!("Dialing {} in place of {}", address, self.target.host_str().unwrap_or(""));
+                address.to_socket_addrs()?.collect::<Vec<_>>()
+            }
+            (None, None) => {
+                let host = self.target.host_str().unwrap_or("").to_ascii_lowercase();
+                let port = self.target.port_or_known_default().unwrap_or(80);
+                match self.config.resolve.get(&(host, port)) {
+                    Some(addresses) => {
+                        info!("Using the --resolve addresses {:?} for {}:{}", addresses, self.target.host_str().unwrap_or(""), port);
+                        addresses.iter().map(|address| SocketAddr::new(*address, port)).collect()
+                    }
+                    None => match self.config.dns_server {
+                        Some(server) => {
+                            let before = Instant::now();
+                            let addresses = dns::lookup(server, self.target.host_str().unwrap_or(""), SOCKET_TIMEOUT)?;
+                            self.dns_time.set(Some(Instant::now().duration_since(before)));
+                            addresses.into_iter().map(|address| SocketAddr::new(address, port)).collect()
+                        }
+                        None => self.target.socket_addrs(|| None)?,
+                    },
+                }
+            }
+        };
+        debug!("Resolved {} to {:?}", self.target.host_str().unwrap_or(""), socket_addresses);
+
+        return Ok(socket_addresses);
+    }
+
+    /* Where `resolve` gets its addresses from, for telling the user */
+    pub fn resolution_source(&self) -> String {
+        let host = self.target.host_str().unwrap_or("").to_ascii_lowercase();
+        let port = self.target.port_or_known_default().unwrap_or(80);
+        return match (&self.config.proxy, &self.config.connect_to, self.config.dns_server) {
+            (Some(proxy), _, _) => format!("the proxy {}", proxy),
+            (None, Some(address), _) => format!("--connect-to {}", address),
+            (None, None, _) if self.config.resolve.contains_key(&(host, port)) => String::from("--resolve"),
+            (None, None, Some(server)) => format!("the DNS server {}", server),
+            (None, None, None) => String::from("the system resolver"),
+        };
+    }
+
+    fn create_regular_connection(&self) -> Result<TcpStream, Box<dyn Error + Send + Sync>> {
+        let socket_addresses = match self.config.pinned_address {
+            Some(address) => vec![address],
+            None => self.resolve()?,
+        };
+
+        let (connection, address) = self.race_connections(&socket_addresses)?;
+        if self.config.show_resolution && !self.connected_once.swap(true, Ordering::Relaxed) {
+            println!("First connection went to {}", address);
+        }
+        connection.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+        connection.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+        match connection.local_addr() {
+            Ok(local) => info!("Connected to {} from {}", address, local),
+            Err(_) => info!("Connected to {}", address),
+        }
+
+        return Ok(connection);
+    }
+
+    /* Connects to whichever of `addresses` answers first, Happy Eyeballs
+       style (RFC 8305), rather than waiting out each address in turn.
+
+       The families take turns, starting with whichever the resolver put first,
+       and each attempt gets CONNECTION_ATTEMPT_DELAY to itself before the next
+       one starts alongside it - or none, if it fails outright. So a dead IPv6
+       address costs a quarter of a second, not the whole connect timeout. */
+    fn race_connections(&self, addresses: &[SocketAddr]) -> Result<(TcpStream, SocketAddr), NotReachableError> {
+        let (interface, timeout) = (self.config.interface, self.config.connect_timeout);
+        let attempt = move |address: &SocketAddr| match interface {
+            Some(local) => connect_from(local, address, timeout),
+            None => TcpStream::connect_timeout(address, timeout),
+        };
+        // the failures are all in the error if every address fails, so on
+        // their own they're only worth a debug line.
+        let mut attempts: Vec<(SocketAddr, String)> = Vec::new();
+        // nothing to race, so no need for a thread.
+        if addresses.len() <= 1 {
+            if let Some(address) = addresses.first() {
+                match attempt(address) {
+                    Ok(connection) => return Ok((connection, *address)),
+                    Err(e) => {
+                        debug!("Error connecting to {}: {}", address, e);
+                        attempts.push((*address, e.to_string()));
+                    }
+                }
+            }
+            return Err(NotReachableError{ attempts });
+        }
+
+        let ordered = interleave_families(addresses);
+        let (sender, receiver) = mpsc::channel();
+        let (mut started, mut failed) = (0, 0);
+        let mut next_start = Instant::now();
+        loop {
+            if started < ordered.len() && (failed == started || Instant::now() >= next_start) {
+                let (address, sender) = (ordered[started], sender.clone());
+                thread::spawn(move || {
+                    // once another attempt has won there's no one listening,
+                    // and this connection is simply dropped.
+                    let _ = sender.send((address, attempt(&address)));
+                });
+                started += 1;
+                next_start = Instant::now() + CONNECTION_ATTEMPT_DELAY;
+            }
+
+            let finished = if started < ordered.len() {
+                receiver.recv_timeout(next_start.saturating_duration_since(Instant::now())).ok()
+            } else {
+                // can't fail, we're holding a sender ourselves.
+                receiver.recv().ok()
+            };
+            match finished {
+                Some((address, Ok(connection))) => {
+                    if ordered.iter().any(|other| other.is_ipv4() != address.is_ipv4()) {
+                        debug!("{} won the race to connect, with {} other attempts still going", address, started - 1 - failed);
+                        let (ipv6, ipv4) = self.family_wins.get();
+                        self.family_wins.set(if address.is_ipv6() { (ipv6 + 1, ipv4) } else { (ipv6, ipv4 + 1) });
+                    }
+                    if !attempts.is_empty() {
+                        info!(
+                            "Connected to {}, after failing to reach {}",
+                            address, attempts.iter().map(|(address, reason)| format!("{} ({})", address, reason)).join(", ")
+                        );
+                    }
+                    return Ok((connection, address));
+                }
+                Some((address, Err(e))) => {
+                    debug!("Error connecting to {}: {}", address, e);
+                    attempts.push((address, e.to_string()));
+                    failed += 1;
+                    if failed == ordered.len() {
+                        return Err(NotReachableError{ attempts });
+                    }
+                }
+                // time for the next attempt to start.
+                None => {}
+            }
+        }
+    }
+
+    fn create_ssl_connection(&self, connector: &SslConnector) -> Result<SslStream<TcpStream>, Box<dyn Error + Send + Sync>> {
+        let stream = self.create_tunnel_connection()?;
+        return self.ssl_handshake(connector, stream);
+    }
+
+    /* A connection that TLS to the target can go over: through a proxy, that
+       means having it open a tunnel first. */
+    fn create_tunnel_connection(&self) -> Result<TcpStream, Box<dyn Error + Send + Sync>> {
+        let mut stream = self.create_regular_connection()?;
+        if self.config.proxy.is_some() {
+            self.open_tunnel(&mut stream)?;
+        }
+
+        return Ok(stream);
+    }
+
+    /* Asks the proxy we're connected to for a tunnel through to the target,
+       and times the round trip - which is all the proxy's doing, and none of
+       the origin's. */
+    fn open_tunnel(&self, stream: &mut TcpStream) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let authority = format!("{}:{}", self.target.host_str().unwrap(), self.target.port_or_known_default().unwrap());
+        let before = Instant::now();
+        let credentials = match self.proxy_authorization() {
+            Some(authorization) => format!("Proxy-Authorization: {}\r\n", authorization),
+            None => String::new(),
+        };
+        stream.write_all(format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n{}\r\n", authority, authority, credentials).as_bytes())?;
+        stream.flush()?;
+        // a byte at a time, so none of the TLS that follows gets read by mistake.
+        let head = read_interim(stream, SOCKET_TIMEOUT)?.unwrap_or_default();
+        let elapsed_time = Instant::now().duration_since(before);
+
+        let (status_code, _) = parse_status_code_and_page(&head);
+        if !(200..=299).contains(&status_code) {
+            let status_line = String::from_utf8_lossy(&head).lines().next().unwrap_or("no response").to_string();
+            if status_code == 407 {
+                return Err(Box::new(ProxyAuthError{ status_line }));
+            }
+            return Err(Box::new(ProxyTunnelError{ status_line }));
+        }
+        debug!("Tunnel to {} open after {}", authority, self.format_duration(elapsed_time));
+        self.tunnel_time.set(Some(elapsed_time));
+
+        return Ok(());
+    }
+
+    /* The `Proxy-Authorization` to send, with `--proxy-user` */
+    fn proxy_authorization(&self) -> Option<String> {
+        return self.config.proxy_user.as_ref()
+            .map(|credentials| format!("Basic {}", openssl::base64::encode_block(credentials.as_bytes())));
+    }
+
+    fn ssl_handshake(&self, connector: &SslConnector, stream: TcpStream) -> Result<SslStream<TcpStream>, Box<dyn Error + Send + Sync>> {
+        let host = self.target.host_str().unwrap();
+        return match connector.connect(host, stream) {
+            Ok(connection) => Ok(connection),
+            Err(e) => Err(Box::new(TlsHandshakeError{ message: e.to_string() })),
+        };
+    }
+
+    /* Times TLS handshakes alone - the TCP connection is made first and left
+       out of the timing, and no HTTP request is ever sent. */
+    fn gather_handshake_statistics(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let connector = tls_connector(self.config.cacert.as_deref())?;
+
+        for index in request_indices(self.config.number_of_requests, self.run_deadline()) {
+            if self.failure_threshold_reached() {
+                warn!("Stopping before handshake {}, as --abort-after-failures has been reached", index + 1);
+                break;
+            }
+            let stream = self.create_tunnel_connection()?;
+            let before = Instant::now();
+            match self.ssl_handshake(&connector, stream) {
+                Ok(mut connection) => {
+                    let elapsed_time = Instant::now().duration_since(before);
+                    debug!("Handshake {}: {}", index + 1, self.format_duration(elapsed_time));
+                    // we're hanging up either way, so a failed close_notify doesn't matter.
+                    let _ = connection.shutdown();
+                    self.emit_event(None, Some(elapsed_time), None, None);
+                    self.outcomes.push((Instant::now(), true));
+                    if let Some(dashboard) = &self.dashboard {
+                        dashboard.record(Some(elapsed_time));
+                    }
+                    self.handshake_times.push(elapsed_time);
+                }
+                Err(x) if !self.config.abort_on_tls_error => {
+                    warn!("Handshake {} failed: {}", index + 1, x);
+                    self.record_failure(x);
+                }
+                Err(x) => return Err(x),
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn gather_http_site_statistics(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        return self.gather_site_statistics(|profiler: &Self| {
+            return profiler.create_regular_connection().map(|stream| Throttled::new(stream, profiler.config.throttle));
+        });
+    }    
+
+    fn gather_https_site_statistics(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        // failing to set up TLS at all is a configuration problem, and no
+        // amount of retrying will fix it - so that's always fatal.
+        let connector = tls_connector(self.config.cacert.as_deref())?;
+        return self.gather_site_statistics(|profiler: &Self| {
+            return profiler.create_ssl_connection(&connector).map(|stream| Throttled::new(stream, profiler.config.throttle));
+        });
+    }
+
+    /* Every measured request's outcome is recorded through one of these two,
+       in the order the requests complete. */
+    fn record_success(&mut self, mut statistic: ResponseProperties) {
+        if !self.reference_checked {
+            self.check_reference(&statistic);
+        }
+        self.emit_event(Some(statistic.status_code), Some(statistic.time_taken), Some(statistic.body_size), None);
+        if self.streaming.is_none() {
+            self.outcomes.push((Instant::now(), true));
+        }
+        self.bytes_read.fetch_add(statistic.header_size + statistic.received_length, Ordering::Relaxed);
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.record(Some(statistic.time_taken));
+        }
+        if let Some(ResponseHook(hook)) = &self.response_hook {
+            hook(&statistic);
+        }
+        let document = mem::take(&mut statistic.document);
+        self.keep_if_longest(statistic.body_size, document);
+        if let Some(streaming) = &self.streaming {
+            streaming.lock().unwrap().record(statistic.time_taken, statistic.status_code, statistic.body_size, statistic.incomplete);
+            return;
+        }
+        self.successful_responses.push(statistic);
+    }
+
+    /* For `--reference-body`: gives up on the whole run, there and then, if the
+       first successful response isn't the one we were told to expect */
+    fn check_reference(&mut self, statistic: &ResponseProperties) {
+        self.reference_checked = true;
+        let reference = match &self.config.reference_body {
+            Some(reference) => reference,
+            None => return,
+        };
+        let body = match self.config.include_headers_in_body {
+            true => statistic.document.splitn(2, "\r\n\r\n").last().unwrap(),
+            false => statistic.document.as_str(),
+        };
+
+        if body == reference.as_str() {
+            info!("The first response matched --reference-body");
+            return;
+        }
+        error!("The first response from {} doesn't match --reference-body:\n{}", self.target, describe_difference(reference, body));
+        process::exit(1);
+    }
+
+    /* Holds on to `document` if its body is the longest seen yet. Ties go to
+       the later one. */
+    fn keep_if_longest(&mut self, body_size: usize, document: String) {
+        if self.longest_document.as_ref().is_none_or(|(longest, _)| body_size >= *longest) {
+            self.longest_document = Some((body_size, document));
+        }
+    }
+
+    fn record_failure(&mut self, failure: Box<dyn Error + Send + Sync>) {
+        self.emit_event(None, None, None, Some(failure.to_string()));
+        if self.streaming.is_none() {
+            self.outcomes.push((Instant::now(), false));
+        }
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.record(None);
+        }
+        self.failures_so_far.fetch_add(1, Ordering::Relaxed);
+        self.failed_responses.push(failure);
+    }
+
+    /* In `--jsonl` mode, writes a line to stdout for the attempt being
+       recorded, as soon as it completes. */
+    fn emit_event(&self, status: Option<i32>, latency: Option<Duration>, size: Option<usize>, error: Option<String>) {
+        if !self.config.jsonl {
+            return;
+        }
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs_f64()).ok();
+        let event = Json::object(vec![
+            ("url", self.target.as_str().into()),
+            ("index", self.completed.fetch_add(1, Ordering::Relaxed).into()),
+            ("status", status.into()),
+            ("latency_ms", latency.map(milliseconds).into()),
+            ("size_bytes", size.into()),
+            ("timestamp", timestamp.into()),
+            ("error", error.into()),
+        ]);
+
+        // a single write of the whole line, so lines never interleave.
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        let _ = handle.write_all(format!("{}\n", event.render()).as_bytes());
+        let _ = handle.flush();
+    }
+
+    /* Times a CORS preflight the way a browser would send one ahead of the real request.
+
+       Preflights always go out on a connection of their own, and any failure
+       is only recorded against the preflight - the measured request still goes
+       ahead afterwards. */
+    fn send_preflight<T, F>(&mut self, connect: &F)
+    where
+        T: Read + Write + ReadDeadline + HalfClose,
+        F: Fn(&Self) -> Result<T, Box<dyn Error + Send + Sync>>,
+    {
+        let outcome = connect(self).and_then(|mut connection| {
+            return self.fetch(&mut connection, &self.preflight_request, false);
+        });
+
+        match outcome {
+            Ok(statistic) => self.preflight_responses.push(statistic),
+            Err(x) => self.failed_preflights.push(x),
+        }
+    }
+
+    /* Follows the redirects from `first` to wherever they end, a fresh
+       connection per hop, and returns the final response with the whole
+       chain on it. Its latency becomes that of every hop added together. */
+    fn follow_redirects(&mut self, first: ResponseProperties) -> Result<ResponseProperties, Box<dyn Error + Send + Sync>> {
+        let mut config = self.config.clone();
+        config.keep_alive = false;
+        // whatever the first response set, e.g. a login's session cookie,
+        // has to go along to where it points.
+        config.cookie_jar = self.cookie_jar.clone();
+        let mut chain = Vec::new();
+        let mut current = self.request_url().clone();
+        let mut visited = vec![current.clone()];
+        let mut response = first;
+
+        while response.classification == StatusClass::Redirect {
+            // a 304, say, redirects nowhere.
+            let location = match response.header("Location") {
+                Some(location) => location.to_string(),
+                None => break,
+            };
+            if chain.len() == MAX_REDIRECTS {
+                return Err(Box::new(TooManyRedirectsError{ limit: MAX_REDIRECTS }));
+            }
+            chain.push(HopTiming{ url: current.to_string(), status_code: response.status_code, time_taken: response.time_taken });
+
+            let next = current.join(&location)?;
+            // a loop would only end at MAX_REDIRECTS otherwise, which hides what's going on.
+            if visited.contains(&next) {
+                return Err(Box::new(RedirectLoopError{ url: next.to_string(), hops: chain.len() }));
+            }
+            visited.push(next.clone());
+            if !["http", "https"].contains(&next.scheme()) {
+                return Err(Box::from(format!("Cannot follow a redirect to {}", next)));
+            }
+            // only these two promise the method and body stay the same.
+            if response.status_code != 307 && response.status_code != 308 {
+                config.body = None;
+                config.method = None;
+            }
+            // `--connect-to` and the like were meant for the host we started on.
+            if next.host_str() != current.host_str() {
+                config.connect_to = None;
+                config.pinned_address = None;
+            }
+            debug!("Following a {} from {} to {}", response.status_code, current, next);
+
+            let mut hop = Profiler::with_config(&next, config.clone());
+            hop.build_requests();
+            response = hop.fetch_once()?;
+            if let Some(jar) = config.cookie_jar.as_mut() {
+                for (_, value) in response.headers.iter().filter(|(key, _)| key.eq_ignore_ascii_case("Set-Cookie")) {
+                    jar.store(&next, value);
+                }
+            }
+            current = next;
+        }
+
+        // and the cookies picked up along the way are kept for the next request.
+        if config.cookie_jar != self.cookie_jar {
+            self.cookie_jar = config.cookie_jar;
+            debug!("Cookie jar updated following redirects");
+            self.build_requests();
+        }
+
+        chain.push(HopTiming{ url: current.to_string(), status_code: response.status_code, time_taken: response.time_taken });
+        response.time_taken = chain.iter().map(|hop| hop.time_taken).sum();
+        response.redirect_chain = chain;
+        return Ok(response);
+    }
+
+    /* A single request on a connection of its own, over whichever of HTTP
+       and HTTPS the target calls for */
+    fn fetch_once(&self) -> Result<ResponseProperties, Box<dyn Error + Send + Sync>> {
+        if self.target.scheme() == "https" {
+            let connector = tls_connector(self.config.cacert.as_deref())?;
+            let mut connection = Throttled::new(self.create_ssl_connection(&connector)?, self.config.throttle);
+            return self.fetch(&mut connection, &self.formatted_request, self.config.head);
+        }
+
+        let mut connection = Throttled::new(self.create_regular_connection()?, self.config.throttle);
+        return self.fetch(&mut connection, &self.formatted_request, self.config.head);
+    }
+
+    /* For `--warm-pool`: opens connections before any request is timed, for
+       the measured requests to take in turn. Setup time left lying around
+       is cleared out, as none of it belongs to a measured request. */
+    fn open_warm_pool<T, F>(&mut self, connect: &F) -> Result<VecDeque<T>, Box<dyn Error + Send + Sync>>
+    where
+        F: Fn(&Self) -> Result<T, Box<dyn Error + Send + Sync>>,
+    {
+        let mut pool = VecDeque::new();
+        let size = match self.config.warm_pool {
+            Some(size) => size,
+            None => return Ok(pool),
+        };
+
+        let before = Instant::now();
+        for _ in 0..size {
+            match connect(self) {
+                Ok(connection) => pool.push_back(connection),
+                Err(x) if x.is::<TlsHandshakeError>() && !self.config.abort_on_tls_error => {
+                    warn!("Could not open a warm connection: {}", x);
+                }
+                Err(x) if x.is::<ProxyAuthError>() => {
+                    warn!("Could not open a warm connection: {}", x);
+                }
+                Err(x) => return Err(x),
+            }
+        }
+        self.warm_pool_time = Instant::now().duration_since(before);
+        self.warm_connections = pool.len();
+        self.tunnel_time.take();
+        self.dns_time.take();
+        info!("Opened {} warm connections in {}", pool.len(), self.format_duration(self.warm_pool_time));
+
+        return Ok(pool);
+    }
+
+    /* For `--sweep`: HEADs every one of `paths` (relative to the target) over
+       as few keep-alive connections as the server allows, with the requests
+       pipelined - all sent before any response is read.
+
+       Each response's latency runs from the requests going out until it was
+       read, so a path answered later in the pipeline waits on those before
+       it. None stands for a path that never got an answer. */
+    pub fn sweep(&self, paths: &[String]) -> Result<Vec<Option<ResponseProperties>>, Box<dyn Error + Send + Sync>> {
+        let absolute_form = self.config.proxy.is_some() && self.target.scheme() != "https";
+        let headers = self.headers();
+        let mut requests = Vec::new();
+        for path in paths.iter() {
+            let target = self.target.join(path)?;
+            requests.push(get_formatted_request(&target, "HEAD", true, None, absolute_form, &headers));
+        }
+
+        if self.target.scheme() == "https" {
+            let connector = tls_connector(self.config.cacert.as_deref())?;
+            return self.pipeline(&requests, |profiler: &Self| {
+                return profiler.create_ssl_connection(&connector).map(|stream| Throttled::new(stream, profiler.config.throttle));
+            });
+        }
+        return self.pipeline(&requests, |profiler: &Self| {
+            return profiler.create_regular_connection().map(|stream| Throttled::new(stream, profiler.config.throttle));
+        });
+    }
+
+    /* Sends HEAD `requests` down a connection all at once, then reads the
+       responses off it in order. Whatever's left unanswered when the server
+       closes the connection goes again on a new one. */
+    fn pipeline<T, F>(&self, requests: &[Vec<u8>], connect: F) -> Result<Vec<Option<ResponseProperties>>, Box<dyn Error + Send + Sync>>
+    where
+        T: Read + Write,
+        F: Fn(&Self) -> Result<T, Box<dyn Error + Send + Sync>>,
+    {
+        let mut responses: Vec<Option<ResponseProperties>> = requests.iter().map(|_| None).collect();
+        let mut next = 0;
+
+        while next < requests.len() {
+            let mut connection = connect(self)?;
+            let before = Instant::now();
+            // a server that's had enough may hang up partway through, but
+            // it'll still have answered the ones it read.
+            for request in requests[next..].iter() {
+                if connection.write_all(request).is_err() {
+                    break;
+                }
+            }
+            let _ = connection.flush();
+
+            let answered_before = next;
+            let mut buffer = Vec::new();
+            let mut chunk = [0; 8192];
+            'reading: while next < requests.len() {
+                while let Framing::Complete(end) = message_framing(&buffer, true) {
+                    let response: Vec<u8> = buffer.drain(..end).collect();
+                    let statistic = parse_response(&response, Instant::now().duration_since(before), true);
+                    let closes_connection = statistic.closes_connection;
+                    responses[next] = Some(statistic);
+                    next += 1;
+                    if closes_connection || next == requests.len() {
+                        break 'reading;
+                    }
+                }
+                match connection.read(&mut chunk) {
+                    Ok(0) => break,
+                    Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) if next > answered_before => break,
+                    Err(e) => return Err(Box::new(e)),
+                }
+            }
+            if next == answered_before {
+                warn!("The server closed the connection without answering, giving up on {} paths", requests.len() - next);
+                break;
+            }
+        }
+
+        return Ok(responses);
+    }
+
+    /* Request loop shared by the HTTP and HTTPS cases - `connect` opens a fresh connection */
+    fn gather_site_statistics<T, F>(&mut self, connect: F) -> Result<(), Box<dyn Error + Send + Sync>>
+    where
+        T: Read + Write + ReadDeadline + PeerAddress + HalfClose,
+        F: Fn(&Self) -> Result<T, Box<dyn Error + Send + Sync>>,
+    {
+        // only ever populated in keep-alive mode, where we hang on to the
+        // connection until the server tells us it's done with it.
+        let mut reusable: Option<T> = None;
+        // where the connection in use, reused or not, is connected to.
+        let mut server_address: Option<SocketAddr> = None;
+        let mut requests_on_connection: u64 = 0;
+        let mut request_limit: Option<u64> = None;
+        let mut connected_before = false;
+        // the last request failed, taking the connection it was on with it.
+        let mut connection_broken = false;
+        // taken by the first response over a newly opened tunnel.
+        let mut tunnel_time: Option<Duration> = None;
+        let mut dns_time: Option<Duration> = None;
+        let mut pool = self.open_warm_pool(&connect)?;
+
+        for index in request_indices(self.config.number_of_requests, self.run_deadline()) {
+            if self.byte_budget_spent() {
+                info!("Stopping before request {}, as --max-total-bytes has been downloaded", index + 1);
+                break;
+            }
+            if self.failure_threshold_reached() {
+                warn!("Stopping before request {}, as --abort-after-failures has been reached", index + 1);
+                break;
+            }
+            if let Some(jitter) = self.config.jitter {
+                let delay = self.rng.duration_below(jitter);
+                debug!("Waiting {:?} before request {}", delay, index + 1);
+                thread::sleep(delay);
+            }
+            // a single `--accept` never changes, so only a list needs rotating.
+            let (agents, accepts) = (&self.config.user_agents, &self.config.accepts);
+            let rotating = !agents.is_empty() || accepts.len() > 1;
+            if !agents.is_empty() {
+                self.user_agent = Some(agents[index as usize % agents.len()].clone());
+            }
+            if accepts.len() > 1 {
+                self.accept = Some(accepts[index as usize % accepts.len()].clone());
+            }
+            if let Some(name) = &self.config.trace_header {
+                self.trace_id = Some(new_trace_id(name));
+            }
+            if !self.config.weighted_paths.is_empty() {
+                let path = self.draw_weighted_path();
+                let url = self.target.join(&path)?;
+                debug!("Request {} goes to {}", index + 1, url);
+                self.path = Some((path, url));
+            }
+            // signatures carry the time they were made, and trace IDs are
+            // one per request, so either way each request is built afresh.
+            if rotating || self.config.aws_sigv4.is_some() || self.trace_id.is_some() || self.path.is_some() {
+                self.build_requests();
+            }
+            if self.config.preflight_origin.is_some() {
+                self.send_preflight(&connect);
+            }
+
+            if let (Some(idle), Some(connection)) = (self.config.idle_probe, reusable.as_mut()) {
+                thread::sleep(idle);
+                if closed_while_idle(connection) {
+                    info!("The server closed our connection after {:?} idle", idle);
+                    self.idle_closures += 1;
+                    reusable = None;
+                } else {
+                    self.idle_survivals += 1;
+                }
+            }
+
+            let started = Instant::now();
+            let mut connection = match reusable.take() {
+                Some(connection) => connection,
+                None => match pool.pop_front() {
+                    // opened before timing began, so there's no reconnecting to count.
+                    Some(connection) => {
+                        connected_before = true;
+                        requests_on_connection = 0;
+                        request_limit = None;
+                        server_address = connection.peer_address().ok();
+                        connection
+                    }
+                    None => {
+                        let before = Instant::now();
+                        let connection = match connect(self) {
+                            Ok(connection) => connection,
+                            // a handshake failing once shouldn't sink the whole run.
+                            Err(x) if x.is::<TlsHandshakeError>() && !self.config.abort_on_tls_error => {
+                                warn!("Request {} failed: {}", index + 1, x);
+                                self.record_failure(x);
+                                continue;
+                            }
+                            // nor should the proxy turning down a tunnel's credentials,
+                            // which the summary counts like a 407 to a plain request.
+                            Err(x) if x.is::<ProxyAuthError>() => {
+                                warn!("Request {} failed: {}", index + 1, x);
+                                self.record_failure(x);
+                                continue;
+                            }
+                            Err(x) => return Err(x),
+                        };
+                        if self.config.keep_alive && connected_before {
+                            if connection_broken {
+                                info!("Reconnected after a request failed on our keep-alive connection");
+                                self.error_reconnections += 1;
+                            } else {
+                                info!("Reconnected after the server closed our keep-alive connection");
+                            }
+                            self.reconnections += 1;
+                            self.reconnection_time += Instant::now().duration_since(before);
+                        }
+                        connected_before = true;
+                        connection_broken = false;
+                        requests_on_connection = 0;
+                        request_limit = None;
+                        server_address = connection.peer_address().ok();
+                        tunnel_time = self.tunnel_time.take();
+                        dns_time = self.dns_time.take();
+                        connection
+                    }
+                },
+            };
+
+            match self.fetch_retrying_empty(&mut connection, &connect, index) {
+                Ok(mut statistic) => {
+                    statistic.total_time = Instant::now().duration_since(started);
+                    statistic.started_at = Some(started);
+                    statistic.user_agent = self.user_agent.clone();
+                    statistic.accept = self.accept.clone();
+                    statistic.path = self.path.as_ref().map(|(path, _)| path.clone());
+                    // the server's own ID for the request, if it has one, is
+                    // the one its traces will be filed under.
+                    if let Some(name) = &self.config.trace_header {
+                        statistic.trace_id = statistic.header(name).map(|id| id.to_string()).or_else(|| self.trace_id.clone());
+                    }
+                    statistic.server_address = server_address;
+                    statistic.tunnel_time = tunnel_time.take();
+                    statistic.dns_time = dns_time.take();
+                    requests_on_connection += 1;
+                    // servers count `max` down as the connection is used, so
+                    // the first value we see is the total the connection allows.
+                    if request_limit.is_none() {
+                        request_limit = statistic.keep_alive_max;
+                    }
+                    let exhausted = statistic.closes_connection
+                        || request_limit.is_some_and(|limit| requests_on_connection >= limit);
+                    if self.config.keep_alive && !exhausted {
+                        reusable = Some(connection);
+                    }
+                    self.absorb_cookies(&statistic);
+                    self.capture_validators(&statistic);
+                    if self.config.follow_redirects && statistic.classification == StatusClass::Redirect {
+                        statistic = match self.follow_redirects(statistic) {
+                            Ok(statistic) => statistic,
+                            Err(x) => {
+                                warn!("Request {} failed following its redirects: {}", index + 1, x);
+                                self.record_failure(x);
+                                continue;
+                            }
+                        };
+                        statistic.total_time = Instant::now().duration_since(started);
+                        statistic.started_at = Some(started);
+                    }
+                    if let (true, Some(declared)) = (statistic.length_mismatch(), statistic.declared_length) {
+                        warn!(
+                            "Request {} declared a Content-Length of {} B but carried {} B",
+                            index + 1, declared, statistic.received_length
+                        );
+                    }
+                    debug!(
+                        "Request {}: status {} in {}, {} B",
+                        index + 1, statistic.status_code, self.format_duration(statistic.time_taken), statistic.body_size
+                    );
+                    self.record_success(statistic);
+                }
+                Err(x) => {
+                    warn!("Request {} failed: {}", index + 1, x);
+                    // the connection is in an unknown state now, so it's
+                    // dropped and the next request opens a new one - the
+                    // run carries on, with only this request lost.
+                    connection_broken = true;
+                    self.record_failure(x);
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    /* Fetches the current request - and with `--retry-empty`, while the
+       server sends nothing back at all, sends it again on a fresh connection,
+       up to `--retries` times, rather than count a status 0 as a success */
+    fn fetch_retrying_empty<T, F>(&mut self, connection: &mut T, connect: &F, index: i64) -> Result<ResponseProperties, Box<dyn Error + Send + Sync>>
+    where
+        T: Read + Write + ReadDeadline + PeerAddress + HalfClose,
+        F: Fn(&Self) -> Result<T, Box<dyn Error + Send + Sync>>,
+    {
+        let mut statistic = self.fetch(connection, &self.formatted_request, self.config.head)?;
+        let mut retries = 0;
+        while self.config.retry_empty && statistic.is_empty() {
+            if retries == self.config.retries {
+                return Err(Box::new(EmptyResponseError{ attempts: retries + 1 }));
+            }
+            retries += 1;
+            self.empty_retries += 1;
+            info!("Request {} came back empty, retrying ({} of {})", index + 1, retries, self.config.retries);
+            // the server has hung up on the old connection.
+            *connection = connect(self)?;
+            statistic = self.fetch(connection, &self.formatted_request, self.config.head)?;
+        }
+
+        return Ok(statistic);
+    }
+
+    /* We only ever send a body with POST */
+    pub fn method(&self) -> &str {
+        if self.config.head {
+            return "HEAD";
+        }
+        if let Some(method) = &self.config.method {
+            return method;
+        }
+        return if self.config.body.is_some() { "POST" } else { "GET" };
+    }
+
+    /* Every header besides `Host` and the framing ones, in the order they're sent.
+       With `--aws-sigv4`, that includes a signature as of right now. */
+    fn headers(&self) -> Vec<(String, String)> {
+        return self.headers_for(self.method(), self.config.body.as_deref());
+    }
+
+    /* The headers for a `method` request with `body` - the real request's
+       method and body, or e.g. a preflight's OPTIONS and none at all */
+    fn headers_for(&self, method: &str, body: Option<&[u8]>) -> Vec<(String, String)> {
+        let accept = self.accept.as_deref()
+            .or_else(|| self.config.accepts.first().map(|accept| accept.as_str()))
+            .unwrap_or(DEFAULT_ACCEPT);
+        let mut headers = vec![
+            (String::from("User-Agent"), self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT).to_string()),
+            (String::from("Accept"), accept.to_string()),
+        ];
+        if let Some(cookie) = self.cookie_jar.as_ref().and_then(|jar| jar.header_for(self.target)) {
+            headers.push((String::from("Cookie"), cookie));
+        }
+        // through a tunnel, the proxy only ever sees the CONNECT.
+        if let (true, Some(authorization)) = (self.target.scheme() != "https", self.proxy_authorization()) {
+            headers.push((String::from("Proxy-Authorization"), authorization));
+        }
+        if self.config.expect_continue && body.is_some() {
+            headers.push((String::from("Expect"), String::from("100-continue")));
+        }
+        if let Some(validators) = &self.validators {
+            headers.extend(validators.iter().cloned());
+        }
+        if let (Some(name), Some(id)) = (&self.config.trace_header, &self.trace_id) {
+            headers.push((name.clone(), id.clone()));
+        }
+        if let Some(signer) = &self.config.aws_sigv4 {
+            headers.extend(signer.sign(method, self.target, body.unwrap_or(&[]), SystemTime::now()));
+        }
+
+        return headers;
+    }
+
+    /* (Re)builds the bytes we send, e.g. after the cookie jar has changed */
+    fn build_requests(&mut self) {
+        // proxies need to be told the whole URL, origin servers just the path -
+        // as does anything going through a tunnel, which ends at the origin.
+        let absolute_form = self.config.proxy.is_some() && self.target.scheme() != "https";
+        self.formatted_request = get_formatted_request(
+            self.request_url(), self.method(), self.config.keep_alive, self.config.body.as_deref(), absolute_form, &self.headers()
+        );
+        if let Some(origin) = &self.config.preflight_origin {
+            // dressed like the real requests, so it warms up the same path through any cache.
+            let mut headers = self.headers_for("OPTIONS", None);
+            headers.push((String::from("Origin"), origin.clone()));
+            headers.push((String::from("Access-Control-Request-Method"), self.method().to_string()));
+            self.preflight_request = get_formatted_request(self.request_url(), "OPTIONS", false, None, absolute_form, &headers);
+        }
+    }
+
+    /* Where the next request goes: the target, or the path drawn for it */
+    fn request_url(&self) -> &Url {
+        return self.path.as_ref().map_or(self.target, |(_, url)| url);
+    }
+
+    /* For `--path-weights`: a path, picked with a chance in proportion to its weight */
+    fn draw_weighted_path(&mut self) -> String {
+        let paths = &self.config.weighted_paths;
+        let total: f64 = paths.iter().map(|(_, weight)| weight).sum();
+        let mut point = self.rng.next_f64() * total;
+        for (path, weight) in paths.iter() {
+            if point < *weight {
+                return path.clone();
+            }
+            point -= weight;
+        }
+
+        // only reachable through rounding, at the very top of the range.
+        return paths[paths.len() - 1].0.clone();
+    }
+
+    /* Files away any cookies the server set, so later requests carry them */
+    fn absorb_cookies(&mut self, statistic: &ResponseProperties) {
+        let jar = match self.cookie_jar.as_mut() {
+            Some(jar) => jar,
+            None => return,
+        };
+
+        let mut changed = false;
+        for (_, value) in statistic.headers.iter().filter(|(key, _)| key.eq_ignore_ascii_case("Set-Cookie")) {
+            changed |= jar.store(self.target, value);
+        }
+        if changed {
+            debug!("Cookie jar updated, now sending: {:?}", jar.header_for(self.target));
+            self.build_requests();
+        }
+    }
+
+    /* For `--repeat-with-cache-headers`: takes the validators from the first
+       full response that has any, so every request after it is conditional */
+    fn capture_validators(&mut self, statistic: &ResponseProperties) {
+        if !self.config.conditional_requests || self.validators.is_some() || statistic.status_code != 200 {
+            return;
+        }
+
+        let mut validators = Vec::new();
+        if let Some(etag) = statistic.header("ETag") {
+            validators.push((String::from("If-None-Match"), etag.to_string()));
+        }
+        if let Some(last_modified) = statistic.header("Last-Modified") {
+            validators.push((String::from("If-Modified-Since"), last_modified.to_string()));
+        }
+        if !validators.is_empty() {
+            debug!("Sending {:?} from now on", validators);
+            self.validators = Some(validators);
+            self.build_requests();
+        }
+    }
+
+    /* Has `hook` called with every successful response as it's recorded, body
+       and all - for embedding the profiler in something with its own idea of
+       what to do with results, e.g. streaming them somewhere.
+
+       With a `concurrency` above 1, every worker calls the same hook from its
+       own thread, as its responses complete - hence `Send + Sync`, and any
+       state it keeps needs a lock or atomics. It runs in the request loop, so
+       a slow hook holds up the next request, though it's never counted in
+       any request's timing. Failures don't reach it. */
+    // the binary has no use for this itself.
+    #[allow(dead_code)]
+    pub fn set_response_hook<F>(&mut self, hook: F) where F: Fn(&ResponseProperties) + Send + Sync + 'static {
+        self.response_hook = Some(ResponseHook(Arc::new(hook)));
+    }
+
+    /* When to stop starting new requests, for a profile run by time not count */
+    fn run_deadline(&self) -> Option<Instant> {
+        return self.config.run_for.map(|budget| Instant::now() + budget);
+    }
+
+    /* For `--max-total-bytes`: whether we've downloaded all we were allowed to */
+    fn byte_budget_spent(&self) -> bool {
+        return self.config.max_total_bytes.is_some_and(|limit| self.bytes_read.load(Ordering::Relaxed) >= limit);
+    }
+
+    /* For `--abort-after-failures`: whether the endpoint has failed often enough to give up on */
+    fn failure_threshold_reached(&self) -> bool {
+        return self.config.abort_after_failures.is_some_and(|limit| self.failures_so_far.load(Ordering::Relaxed) >= limit);
+    }
+
+    /* Whether anything in this run draws on `rng`, so its seed matters */
+    fn uses_randomness(&self) -> bool {
+        return self.config.jitter.is_some() || !self.config.weighted_paths.is_empty();
+    }
+
+    /* Main entrypoint to `Profiler` */
+    pub fn profile(&mut self) {
+        if self.uses_randomness() {
+            info!("Random seed for {}: {}", self.target, self.seed);
+        }
+        let started = Instant::now();
+        if self.config.concurrency > 1 && (self.config.number_of_requests > 1 || self.config.run_for.is_some()) {
+            self.profile_concurrently();
+        } else {
+            self.profile_sequentially();
+        }
+        if let Some(dashboard) = &self.dashboard {
+            dashboard.finish();
+        }
+        // throughput is about the measured requests, not the warm-up.
+        self.wall_time = Instant::now().duration_since(started).saturating_sub(self.warm_pool_time);
+    }
+
+    /* Splits the requests between `concurrency` workers - each a profiler of
+       its own, on connections of its own - and folds everything they gathered
+       back into this one. */
+    fn profile_concurrently(&mut self) {
+        // with a time budget, every worker just keeps going until it's spent.
+        let workers = match self.config.run_for {
+            Some(_) => self.config.concurrency as i64,
+            None => (self.config.concurrency as i64).min(self.config.number_of_requests),
+        };
+        let target = self.target;
+
+        let finished: Vec<Profiler> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers).map(|worker| {
+                let mut config = self.config.clone();
+                config.concurrency = 1;
+                // a seed of their own each, or they'd all wait in lockstep.
+                config.seed = Some(self.seed.wrapping_add(worker as u64));
+                config.number_of_requests = self.config.number_of_requests / workers
+                    + if worker < self.config.number_of_requests % workers { 1 } else { 0 };
+                config.warm_pool = self.config.warm_pool.map(|size| {
+                    size / workers as usize + if (worker as usize) < size % workers as usize { 1 } else { 0 }
+                });
+                let completed = Arc::clone(&self.completed);
+                let response_hook = self.response_hook.clone();
+                let dashboard = self.dashboard.clone();
+                let streaming = self.streaming.clone();
+                let bytes_read = Arc::clone(&self.bytes_read);
+                let failures_so_far = Arc::clone(&self.failures_so_far);
+                let connected_once = Arc::clone(&self.connected_once);
+
+                return scope.spawn(move || {
+                    let mut profiler = Profiler::with_config(target, config);
+                    profiler.completed = completed;
+                    profiler.response_hook = response_hook;
+                    profiler.dashboard = dashboard;
+                    profiler.streaming = streaming;
+                    profiler.bytes_read = bytes_read;
+                    profiler.failures_so_far = failures_so_far;
+                    profiler.connected_once = connected_once;
+                    profiler.profile_sequentially();
+                    return profiler;
+                });
+            }).collect();
+
+            // a worker only panics if we have a bug, so pass it straight on.
+            return handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+        });
+
+        for worker in finished {
+            self.successful_responses.extend(worker.successful_responses);
+            if let Some((body_size, document)) = worker.longest_document {
+                self.keep_if_longest(body_size, document);
+            }
+            self.failed_responses.extend(worker.failed_responses);
+            self.handshake_times.extend(worker.handshake_times);
+            self.outcomes.extend(worker.outcomes);
+            self.preflight_responses.extend(worker.preflight_responses);
+            self.failed_preflights.extend(worker.failed_preflights);
+            self.reconnections += worker.reconnections;
+            self.reconnection_time += worker.reconnection_time;
+            self.error_reconnections += worker.error_reconnections;
+            self.warm_connections += worker.warm_connections;
+            self.idle_survivals += worker.idle_survivals;
+            self.idle_closures += worker.idle_closures;
+            self.empty_retries += worker.empty_retries;
+            let ((ipv6, ipv4), (worker_ipv6, worker_ipv4)) = (self.family_wins.get(), worker.family_wins.get());
+            self.family_wins.set((ipv6 + worker_ipv6, ipv4 + worker_ipv4));
+            // the workers opened their pools side by side.
+            self.warm_pool_time = self.warm_pool_time.max(worker.warm_pool_time);
+            // workers each had a copy of the jar; the last one's is as good as any.
+            if worker.cookie_jar.is_some() {
+                self.cookie_jar = worker.cookie_jar;
+            }
+        }
+        self.outcomes.sort_by_key(|(completed_at, _)| *completed_at);
+    }
+
+    fn profile_sequentially(&mut self) {
+        self.build_requests();
+        if self.config.handshake_only {
+            if let Err(x) = self.gather_handshake_statistics() {
+                error!("Encountered unfixable error creating HTTPS connection: {}", x);
+                process::exit(1);
+            };
+        } else if self.target.scheme() == "https" {
+            if let Err(x) = self.gather_https_site_statistics() {
+                error!("Encountered unfixable error creating HTTPS connection: {}", x);
+                process::exit(1);
+            };
+        } else {
+            if let Err(y) = self.gather_http_site_statistics() {
+                error!("Encountered unfixable error creating HTTP connection: {}", y);
+                process::exit(1);
+            };
+        }
+    }
+
+    fn format_duration(&self, duration: Duration) -> String {
+        return format_duration(self.config.unit, duration);
+    }
+
+    /* For HEAD requests, where the headers are all there is to measure */
+    fn publish_header_sizes(&self) {
+        let sizes: Vec<usize> = self.successful_responses.iter().map(|response| response.header_size).collect();
+        match (sizes.iter().min(), sizes.iter().max()) {
+            (Some(smallest), Some(largest)) => {
+                println!("Smallest header size: {} B", smallest);
+                println!("Largest header size: {} B", largest);
+            }
+            _ => println!("No header sizes recorded (no successful responses)"),
+        }
+    }
+
+    /* Shows what the server made of each `Accept` we sent: how quickly it
+       answered, how much it sent, and as what */
+    fn publish_content_negotiation(&self) {
+        let mut responses_by_accept: BTreeMap<&str, Vec<&ResponseProperties>> = BTreeMap::new();
+        for response in self.successful_responses.iter() {
+            if let Some(accept) = response.accept.as_deref() {
+                responses_by_accept.entry(accept).or_default().push(response);
+            }
+        }
+        if responses_by_accept.is_empty() {
+            println!("No content negotiation recorded (no successful responses)");
+            return;
+        }
+
+        let width = responses_by_accept.keys().map(|accept| accept.len()).max().unwrap_or(0);
+        println!("Responses by Accept header:");
+        for (accept, responses) in responses_by_accept.iter() {
+            let durations: Vec<Duration> = responses.iter().map(|i| i.time_taken).collect();
+            let mean_size = responses.iter().map(|i| i.body_size).sum::<usize>() as f64 / responses.len() as f64;
+            let content_types = responses.iter()
+                .map(|i| i.content_type().unwrap_or_else(|| String::from("(none)")))
+                .unique()
+                .join(", ");
+            // safe to unwrap, every value here has at least one response.
+            println!(
+                "  {:<width$}  {:>6} responses  {:>14}  {:>12.1} B  got {}",
+                accept, responses.len(), self.format_duration(mean(&durations).unwrap()), mean_size, content_types,
+                width = width
+            );
+        }
+    }
+
+    /* Which HTTP versions the server answered with - a load balancer or proxy
+       handling them inconsistently shows up as a mix */
+    fn publish_http_versions(&self) {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for response in self.successful_responses.iter() {
+            if let Some(version) = response.http_version.as_deref() {
+                *counts.entry(version).or_default() += 1;
+            }
+        }
+        if counts.is_empty() {
+            return;
+        }
+
+        println!("HTTP versions: {}", counts.iter().map(|(version, count)| format!("{} {}", version, count)).join(", "));
+        // we always ask in 1.1, so a 1.0 answer alongside 1.1 ones means
+        // something on the way is treating some requests differently.
+        if let (Some(downgraded), true) = (counts.get("HTTP/1.0"), counts.len() > 1) {
+            println!(
+                "{}",
+                paint(format!("{} responses were downgraded to HTTP/1.0, while the rest weren't", downgraded), Color::Yellow)
+            );
+        }
+    }
+
+    /* Breaks down latency by the address that answered, to show up an uneven
+       backend behind round-robin DNS */
+    fn publish_latency_by_server_address(&self) {
+        let mut durations_by_address: BTreeMap<SocketAddr, Vec<Duration>> = BTreeMap::new();
+        for response in self.successful_responses.iter() {
+            if let Some(address) = response.server_address {
+                durations_by_address.entry(address).or_default().push(response.time_taken);
+            }
+        }
+        // with only the one address, there's nothing to compare.
+        if durations_by_address.len() < 2 {
+            return;
+        }
+
+        println!("Response time by server address:");
+        println!("  {:<40}  {:>9}  {:>14}  {:>14}", "address", "responses", "mean", "p99");
+        for (address, durations) in durations_by_address.iter() {
+            let sorted_durations: Vec<Duration> = durations.iter().cloned().sorted().collect();
+            // safe to unwrap, every address here has at least one response.
+            println!(
+                "  {:<40}  {:>9}  {:>14}  {:>14}",
+                address.to_string(), durations.len(),
+                self.format_duration(mean(durations).unwrap()),
+                self.format_duration(percentile(&sorted_durations, 99.0).unwrap())
+            );
+        }
+    }
+
+    /* Breaks down mean latency by the user agent each request claimed to be */
+    fn publish_latency_by_user_agent(&self) {
+        let mut durations_by_agent: BTreeMap<&str, Vec<Duration>> = BTreeMap::new();
+        for response in self.successful_responses.iter() {
+            if let Some(agent) = response.user_agent.as_deref() {
+                durations_by_agent.entry(agent).or_default().push(response.time_taken);
+            }
+        }
+        if durations_by_agent.is_empty() {
+            println!("No latency by user agent recorded (no successful responses)");
+            return;
+        }
+
+        println!("Mean response time by user agent:");
+        for (agent, durations) in durations_by_agent.iter() {
+            // safe to unwrap, every agent here has at least one response.
+            println!(
+                "  {:>14}  {:>6} responses  {}",
+                self.format_duration(mean(durations).unwrap()), durations.len(), agent
+            );
+        }
+    }
+
+    /* For `--path-weights`: each path's share of the traffic, and how it fared */
+    fn publish_latency_by_path(&self) {
+        let mut durations_by_path: BTreeMap<&str, Vec<Duration>> = BTreeMap::new();
+        for response in self.successful_responses.iter() {
+            if let Some(path) = response.path.as_deref() {
+                durations_by_path.entry(path).or_default().push(response.time_taken);
+            }
+        }
+        if durations_by_path.is_empty() {
+            println!("No latency by path recorded (no successful responses)");
+            return;
+        }
+
+        let total_weight: f64 = self.config.weighted_paths.iter().map(|(_, weight)| weight).sum();
+        let width = durations_by_path.keys().map(|path| path.len()).max().unwrap_or(0);
+        println!("Response times by path:");
+        for (path, weight) in self.config.weighted_paths.iter() {
+            let durations = match durations_by_path.get_mut(path.as_str()) {
+                Some(durations) => durations,
+                None => {
+                    println!("  {:<width$}  no successful responses (weight {:.1}%)", path, weight / total_weight * 100.0, width = width);
+                    continue;
+                }
+            };
+            durations.sort();
+            // safe to unwrap, every path here has at least one response.
+            println!(
+                "  {:<width$}  {:>6} responses ({:>5.1}%, weight {:.1}%)  mean {:>14}  p99 {:>14}",
+                path, durations.len(), durations.len() as f64 / self.successful_responses.len() as f64 * 100.0,
+                weight / total_weight * 100.0,
+                self.format_duration(mean(durations).unwrap()), self.format_duration(percentile(durations, 99.0).unwrap()),
+                width = width
+            );
+        }
+    }
+
+    /* For `--read-chunk-size`: how fast bodies came in once they'd started,
+       and the longest any response sat waiting for its next piece */
+    fn publish_transfer_rates(&self, chunk_size: usize) {
+        let rates: Vec<f64> = self.successful_responses.iter().filter_map(|response| response.transfer_rate).collect();
+        if rates.is_empty() {
+            println!("No transfer rates measured (every response arrived in a single {} B read)", chunk_size);
+            return;
+        }
+
+        let mean_rate = rates.iter().sum::<f64>() / rates.len() as f64;
+        let slowest = rates.iter().cloned().fold(f64::INFINITY, f64::min);
+        let fastest = rates.iter().cloned().fold(0.0, f64::max);
+        println!(
+            "Sustained transfer rate, in {} B reads: mean {}, slowest {}, fastest {} ({} of {} responses took more than one read)",
+            chunk_size, format_rate(mean_rate), format_rate(slowest), format_rate(fastest), rates.len(), self.successful_responses.len()
+        );
+        // safe to unwrap, any response with a rate had a gap between reads.
+        let stall = self.successful_responses.iter().filter_map(|response| response.longest_stall).max().unwrap();
+        println!("Longest stall between reads: {}", self.format_duration(stall));
+    }
+
+    /* How fast request bodies went out, apart from however long the server
+       then took to answer */
+    fn publish_upload_rates(&self) {
+        let rates: Vec<f64> = self.successful_responses.iter().filter_map(|response| response.upload_rate).collect();
+        if rates.is_empty() {
+            println!("No upload throughput measured (no request bodies were sent in full)");
+            return;
+        }
+
+        let mean_rate = rates.iter().sum::<f64>() / rates.len() as f64;
+        let slowest = rates.iter().cloned().fold(f64::INFINITY, f64::min);
+        println!(
+            "Upload throughput: mean {}, slowest {} ({} of {} responses)",
+            format_rate(mean_rate), format_rate(slowest), rates.len(), self.successful_responses.len()
+        );
+    }
+
+    /* Breaks down mean response size by `Content-Type`, so big images don't hide behind tiny JSON */
+    fn publish_sizes_by_content_type(&self) {
+        if self.successful_responses.is_empty() {
+            return;
+        }
+
+        let mut sizes_by_type: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for response in self.successful_responses.iter() {
+            let content_type = response.content_type().unwrap_or_else(|| String::from("(none)"));
+            sizes_by_type.entry(content_type).or_default().push(response.body_size);
+        }
+
+        let width = sizes_by_type.keys().map(|key| key.len()).max().unwrap_or(0);
+        println!("Mean size by content type:");
+        for (content_type, sizes) in sizes_by_type.iter() {
+            let mean_size = sizes.iter().sum::<usize>() as f64 / sizes.len() as f64;
+            println!("  {:<width$}  {:>6} responses  {:>12.1} B", content_type, sizes.len(), mean_size, width = width);
+        }
+    }
+
+    /* Compares the first (likely uncached) response against the rest */
+    fn publish_cache_comparison(&self) {
+        if self.successful_responses.len() < 2 {
+            println!("No cache comparison possible (needs at least 2 successful responses)");
+            return;
+        }
+
+        // workers' responses are merged one worker after another, so the
+        // run's first request is whichever started earliest, not the first listed.
+        let (first, _) = self.successful_responses.iter().enumerate()
+            .min_by_key(|(index, response)| (response.started_at, *index)).unwrap();
+        let cold = self.successful_responses[first].time_taken;
+        let warm_durations = self.successful_responses.iter().enumerate()
+            .filter(|(index, _)| *index != first)
+            .map(|(_, i)| i.time_taken).sorted().collect::<Vec<Duration>>();
+        // safe to unwrap, we've just checked there's at least one warm response.
+        let warm = median(&warm_durations).unwrap();
+
+        println!("Cold (first) response time: {}", self.format_duration(cold));
+        println!("Warm (median of remaining {}) response time: {}", warm_durations.len(), self.format_duration(warm));
+        if cold >= warm {
+            println!("Estimated cache benefit: {}", self.format_duration(cold - warm));
+        } else {
+            println!("Estimated cache benefit: none (warm responses were {} slower)", self.format_duration(warm - cold));
+        }
+    }
+
+    /* Tallies the responses that failed each `--expect-header` check */
+    fn publish_header_assertions(&self) {
+        let failing = self.successful_responses.iter()
+            .filter(|response| !self.config.expected_headers.iter().all(|expected| header_matches(response, expected)))
+            .count();
+        let color = if failing > 0 { Color::Red } else { Color::Green };
+        println!(
+            "Header assertion failures: {} of {} responses",
+            paint(failing, color), self.successful_responses.len()
+        );
+
+        for expected in self.config.expected_headers.iter() {
+            let missing = self.successful_responses.iter().filter(|response| !header_matches(response, expected)).count();
+            let description = match expected {
+                (name, Some(value)) => format!("{}: {}", name, value),
+                (name, None) => name.clone(),
+            };
+            println!("  {:<40} failed on {}", description, missing);
+        }
+    }
+
+    fn publish_length_mismatches(&self) {
+        let mismatched: Vec<&ResponseProperties> = self.successful_responses.iter().filter(|i| i.length_mismatch()).collect();
+        let truncated = mismatched.iter().filter(|i| i.declared_length.is_some_and(|declared| i.received_length < declared)).count();
+        let color = if mismatched.is_empty() { Color::Green } else { Color::Red };
+
+        println!(
+            "Responses not matching their Content-Length: {} ({} truncated, {} over-long)",
+            paint(mismatched.len(), color), truncated, mismatched.len() - truncated
+        );
+    }
+
+    /* Whether any chunked response carried trailers, and which */
+    fn publish_trailers(&self) {
+        let with_trailers: Vec<&ResponseProperties> = self.successful_responses.iter()
+            .filter(|response| !response.trailers.is_empty())
+            .collect();
+        if with_trailers.is_empty() {
+            return;
+        }
+
+        let names: BTreeSet<&str> = with_trailers.iter()
+            .flat_map(|response| response.trailers.iter().map(|(name, _)| name.as_str()))
+            .collect();
+        println!(
+            "Responses with trailers: {} of {} ({})",
+            with_trailers.len(), self.successful_responses.len(), names.into_iter().join(", ")
+        );
+    }
+
+    /* How many different bodies came back, and how often each did */
+    fn publish_distinct_bodies(&self, distinct_bodies: usize) {
+        let mut counts: BTreeMap<u64, usize> = BTreeMap::new();
+        for response in self.successful_responses.iter() {
+            *counts.entry(response.body_hash).or_default() += 1;
+        }
+        // a non-deterministic server is the interesting case, so colour it as a warning.
+        let color = if distinct_bodies > 1 { Color::Yellow } else { Color::Green };
+        println!("Distinct response bodies: {}", paint(distinct_bodies, color));
+
+        let total = self.successful_responses.len();
+        let shown = 10;
+        for (hash, count) in counts.iter().sorted_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0))).take(shown) {
+            println!("  {:016x}  {:>6} responses  {:>5.1}%", hash, count, *count as f64 / total as f64 * 100_f64);
+        }
+        if counts.len() > shown {
+            println!("  ... and {} more", counts.len() - shown);
+        }
+    }
+
+    /* How the server dealt with `Expect: 100-continue` */
+    fn publish_continue_waits(&self) {
+        let waits: Vec<Duration> = self.successful_responses.iter().filter_map(|i| i.continue_wait).collect();
+        match mean(&waits) {
+            Some(wait) => println!(
+                "100 Continue received for {} of {} responses, after a mean wait of {}",
+                waits.len(), self.successful_responses.len(), self.format_duration(wait)
+            ),
+            None => println!(
+                "100 Continue never received (bodies went after {} without one, or weren't wanted)",
+                self.format_duration(EXPECT_CONTINUE_WAIT)
+            ),
+        }
+    }
+
+    /* Splits the time spent on the proxy's tunnel from the time spent on
+       requests through it, for telling which of the two is slow */
+    fn publish_tunnel_times(&self) {
+        let tunnels: Vec<Duration> = self.successful_responses.iter().filter_map(|i| i.tunnel_time).sorted().collect();
+        let requests: Vec<Duration> = self.successful_responses.iter().map(|i| i.time_taken).collect();
+        let describe = |duration: Option<Duration>| duration.map_or(String::from("-"), |d| self.format_duration(d));
+        println!(
+            "Proxy CONNECT: {} tunnels opened, mean {}, median {}, slowest {} (requests through them: mean {})",
+            tunnels.len(), describe(mean(&tunnels)), describe(median(&tunnels)), describe(tunnels.last().cloned()),
+            describe(mean(&requests))
+        );
+    }
+
+    fn publish_dns_times(&self, server: SocketAddr) {
+        let lookups: Vec<Duration> = self.successful_responses.iter().filter_map(|i| i.dns_time).sorted().collect();
+        let describe = |duration: Option<Duration>| duration.map_or(String::from("-"), |d| self.format_duration(d));
+        println!(
+            "DNS lookups via {}: {}, mean {}, median {}, slowest {}",
+            server, lookups.len(), describe(mean(&lookups)), describe(median(&lookups)), describe(lookups.last().cloned())
+        );
+    }
+
+    /* For each step along the redirect chains, how long it took on average -
+       with the first response's URL and status there as an example */
+    fn publish_redirect_chains(&self) {
+        let chains: Vec<&Vec<HopTiming>> = self.successful_responses.iter()
+            .map(|i| &i.redirect_chain).filter(|chain| !chain.is_empty()).collect();
+        if chains.is_empty() {
+            println!("Redirects followed: none");
+            return;
+        }
+
+        let hops: usize = chains.iter().map(|chain| chain.len()).sum();
+        println!(
+            "Redirects followed: {} of {} responses, {:.1} requests per chain on average",
+            chains.len(), self.successful_responses.len(), hops as f64 / chains.len() as f64
+        );
+        let longest = chains.iter().map(|chain| chain.len()).max().unwrap_or(0);
+        for position in 0..longest {
+            let at_position: Vec<&HopTiming> = chains.iter().filter_map(|chain| chain.get(position)).collect();
+            let times: Vec<Duration> = at_position.iter().map(|hop| hop.time_taken).collect();
+            println!(
+                "  hop {}: mean {} over {} responses (e.g. {} from {})",
+                position + 1, self.format_duration(mean(&times).unwrap()), at_position.len(),
+                at_position[0].status_code, at_position[0].url
+            );
+        }
+    }
+
+    /* How revalidating compared with fetching in full, for `--repeat-with-cache-headers` */
+    fn publish_conditional_requests(&self) {
+        let durations_with_status = |status_code: i32| -> Vec<Duration> {
+            return self.successful_responses.iter()
+                .filter(|response| response.status_code == status_code)
+                .map(|response| response.time_taken)
+                .collect();
+        };
+        let (not_modified, full) = (durations_with_status(304), durations_with_status(200));
+        let format_mean = |durations: &[Duration]| mean(durations).map_or(String::from("n/a"), |mean| self.format_duration(mean));
+
+        println!(
+            "Conditional requests: {} answered 304 Not Modified (mean {}), {} answered 200 in full (mean {})",
+            not_modified.len(), format_mean(&not_modified), full.len(), format_mean(&full)
+        );
+        if let (Some(not_modified_mean), Some(full_mean)) = (mean(&not_modified), mean(&full)) {
+            let saving = 100.0 * (1.0 - not_modified_mean.as_secs_f64() / full_mean.as_secs_f64());
+            println!("  Revalidating took {:.1}% less time than fetching in full", saving);
+        } else if not_modified.is_empty() {
+            let validated = self.successful_responses.iter()
+                .any(|response| response.header("ETag").is_some() || response.header("Last-Modified").is_some());
+            if validated {
+                println!("  The server never answered 304, despite being sent its own validators");
+            } else {
+                println!("  No response had an ETag or Last-Modified, so requests were never conditional");
+            }
+        }
+    }
+
+    fn publish_idle_probe(&self, idle: Duration) {
+        let verdict = match (self.idle_survivals, self.idle_closures) {
+            (0, 0) => String::from("unknown, as no connection was ever reused"),
+            (_, 0) => format!("at least {}", self.format_duration(idle)),
+            (0, _) => format!("under {}", self.format_duration(idle)),
+            (_, _) => format!("around {}, as it closed some idle connections and not others", self.format_duration(idle)),
+        };
+        println!(
+            "Idle probe: {} connections survived {} idle, {} were closed by the server - its idle timeout is {}",
+            self.idle_survivals, self.format_duration(idle), self.idle_closures, verdict
+        );
+    }
+
+    /* The trace IDs of the slowest few requests, to go and find in a tracing system */
+    fn publish_slowest_traces(&self, name: &str) {
+        let slowest: Vec<&ResponseProperties> = self.successful_responses.iter()
+            .filter(|i| i.trace_id.is_some())
+            .sorted_by(|a, b| b.time_taken.cmp(&a.time_taken))
+            .take(SLOWEST_TRACES)
+            .collect();
+        if slowest.is_empty() {
+            println!("Slowest requests by {}: none", name);
+            return;
+        }
+
+        println!("Slowest requests by {}:", name);
+        for response in slowest {
+            println!("  {:>14}  {}", self.format_duration(response.time_taken), response.trace_id.as_deref().unwrap());
+        }
+    }
+
+    fn publish_slow_responses(&self, threshold: Duration) {
+        let slow = self.successful_responses.iter().filter(|i| i.time_taken > threshold).count();
+        let percentage = if self.successful_responses.is_empty() {
+            0_f64
+        } else {
+            slow as f64 / self.successful_responses.len() as f64 * 100_f64
+        };
+        let color = if slow > 0 { Color::Yellow } else { Color::Green };
+
+        println!(
+            "Slow responses (over {}): {} ({} of successful responses)",
+            self.format_duration(threshold), slow, paint(format!("{}%", percentage), color)
+        );
+    }
+
+    /* Percentiles of `total_time`, i.e. with DNS, connecting and TLS counted in */
+    fn publish_total_times(&self) {
+        let sorted_totals = self.successful_responses.iter()
+            .map(|i| i.total_time).sorted().collect::<Vec<Duration>>();
+        if sorted_totals.is_empty() {
+            println!("No total times recorded (no successful responses)");
+            return;
+        }
+
+        // safe to unwrap, there's at least one total.
+        for p in [50.0, 90.0, 99.0].iter() {
+            println!(
+                "p{} total time (including connection setup): {}",
+                p, self.format_duration(percentile(&sorted_totals, *p).unwrap())
+            );
+        }
+    }
+
+    fn publish_preflights(&self, request_mean: Option<Duration>) {
+        let preflight_durations: Vec<Duration> = self.preflight_responses.iter().map(|i| i.time_taken).collect();
+        match (mean(&preflight_durations), request_mean) {
+            (Some(preflight), Some(request)) => println!(
+                "Mean preflight (OPTIONS) latency: {} vs mean request latency: {}",
+                self.format_duration(preflight), self.format_duration(request)
+            ),
+            (Some(preflight), None) => println!("Mean preflight (OPTIONS) latency: {}", self.format_duration(preflight)),
+            (None, _) => println!("No mean preflight latency recorded (no successful preflights)"),
+        }
+        println!("Preflight errors encountered, if any: {:?}", self.failed_preflights);
+    }
+
+    /* Shows whether failures cluster somewhere in the run, e.g. all at the start */
+    fn publish_success_by_decile(&self) {
+        let total = self.outcomes.len();
+        if total == 0 {
+            return;
+        }
+
+        // runs shorter than ten requests just get one bucket per request.
+        let buckets = total.min(10);
+        println!("Success rate by portion of the run:");
+        for bucket in 0..buckets {
+            let start = bucket * total / buckets;
+            let end = (bucket + 1) * total / buckets;
+            let succeeded = self.outcomes[start..end].iter().filter(|(_, succeeded)| *succeeded).count();
+            let percentage = succeeded as f64 / (end - start) as f64 * 100_f64;
+            println!(
+                "  {:>3}-{:>3}%: {}/{} succeeded ({})",
+                start * 100 / total, end * 100 / total, succeeded, end - start,
+                paint(format!("{:.1}%", percentage), success_rate_color(percentage))
+            );
+        }
+    }
+
+    /* How soon the headers were all in, apart from however long the body
+       took after them - streamed bodies show up as a big difference */
+    fn publish_time_to_headers(&self) {
+        let mut header_times: Vec<Duration> = self.successful_responses.iter().filter_map(|response| response.time_to_headers).collect();
+        if header_times.is_empty() {
+            return;
+        }
+        header_times.sort();
+        let mut body_times: Vec<Duration> = self.successful_responses.iter()
+            .filter_map(|response| response.time_to_headers.map(|headers| response.time_taken.saturating_sub(headers)))
+            .collect();
+        body_times.sort();
+
+        // safe to unwrap, both have at least one time in them.
+        println!(
+            "Time to headers: median {}, p90 {}, p99 {} (bodies then took a median {} more)",
+            self.format_duration(median(&header_times).unwrap()),
+            self.format_duration(percentile(&header_times, 90.0).unwrap()),
+            self.format_duration(percentile(&header_times, 99.0).unwrap()),
+            self.format_duration(median(&body_times).unwrap())
+        );
+    }
+
+    /* For `--buckets`: what share of every request made finished under each
+       threshold - failures count as missing them all */
+    fn publish_latency_buckets(&self, total_requests: usize) {
+        if total_requests == 0 {
+            return;
+        }
+
+        let thresholds = &self.config.latency_buckets;
+        let labels: Vec<String> = thresholds.iter().map(|threshold| format!("< {}", self.format_duration(*threshold)))
+            .chain(thresholds.last().map(|threshold| format!(">= {}", self.format_duration(*threshold))))
+            .chain(std::iter::once(String::from("failed")))
+            .collect();
+        let mut counts = vec![0usize; labels.len()];
+        for response in self.successful_responses.iter() {
+            let bucket = thresholds.iter().position(|threshold| response.time_taken < *threshold).unwrap_or(thresholds.len());
+            counts[bucket] += 1;
+        }
+        counts[thresholds.len() + 1] = total_requests - self.successful_responses.len();
+
+        let width = labels.iter().map(|label| label.len()).max().unwrap_or(0);
+        let share = |count: usize| count as f64 / total_requests as f64 * 100_f64;
+        println!("Requests by latency:");
+        let mut within = 0;
+        for (bucket, (label, count)) in labels.iter().zip(counts.iter()).enumerate() {
+            if bucket < thresholds.len() {
+                within += count;
+                println!(
+                    "  {:<width$}  {:>6}  {:>5.1}%  ({:.1}% of all requests within)",
+                    label, count, share(*count), share(within),
+                    width = width
+                );
+            } else {
+                println!("  {:<width$}  {:>6}  {:>5.1}%", label, count, share(*count), width = width);
+            }
+        }
+    }
+
+    /* For `--apdex-threshold`: the Apdex score against target time T - a
+       response within T satisfies, within 4T is tolerated, and anything
+       slower frustrates, as does a failure */
+    fn publish_apdex(&self, threshold: Duration, total_requests: usize) {
+        if total_requests == 0 {
+            return;
+        }
+
+        let satisfied = self.successful_responses.iter().filter(|response| response.time_taken <= threshold).count();
+        let tolerating = self.successful_responses.iter()
+            .filter(|response| response.time_taken > threshold && response.time_taken <= threshold * 4)
+            .count();
+        let frustrated = total_requests - satisfied - tolerating;
+        let score = (satisfied as f64 + tolerating as f64 / 2_f64) / total_requests as f64;
+
+        // the ratings are the Apdex Alliance's own.
+        let (rating, color) = match score {
+            score if score >= 0.94 => ("excellent", Color::Green),
+            score if score >= 0.85 => ("good", Color::Green),
+            score if score >= 0.70 => ("fair", Color::Yellow),
+            score if score >= 0.50 => ("poor", Color::Red),
+            _ => ("unacceptable", Color::Red),
+        };
+        println!(
+            "Apdex (T = {}): {} - {} satisfied, {} tolerating, {} frustrated ({} of them failed)",
+            self.format_duration(threshold), paint(format!("{:.2} {}", score, rating), color),
+            satisfied, tolerating, frustrated, total_requests - self.successful_responses.len()
+        );
+    }
+
+    /* For `--handshake-only`: how many TLS handshakes completed, how long
+       they took - fastest, mean, p50, p90, p99 and slowest - and why any failed */
+    fn publish_handshakes(&self) {
+        let total_handshakes = self.handshake_times.len() + self.failed_responses.len();
+        let sorted_times = self.handshake_times.iter().cloned().sorted().collect::<Vec<Duration>>();
+
+        let completed = self.handshake_times.len() as f64 / total_handshakes as f64 * 100_f64;
+
+        println!("Number of handshakes: {}", total_handshakes);
+        println!(
+            "Percentage of handshakes completed: {}",
+            paint(format!("{}%", completed), success_rate_color(completed))
+        );
+        if sorted_times.is_empty() {
+            println!("No handshake times recorded (no successful handshakes)");
+        } else {
+            // safe to unwrap, none of these are empty.
+            println!("Fastest handshake time: {}", self.format_duration(sorted_times[0]));
+            println!("Mean handshake time: {}", self.format_duration(mean(&sorted_times).unwrap()));
+            for p in [50.0, 90.0, 99.0].iter() {
+                println!("p{} handshake time: {}", p, self.format_duration(percentile(&sorted_times, *p).unwrap()));
+            }
+            println!("Slowest handshake time: {}", self.format_duration(*sorted_times.last().unwrap()));
+        }
+        if self.config.report_deciles {
+            self.publish_success_by_decile();
+        }
+        println!("Handshake errors encountered, if any: {}", paint_errors(&self.failed_responses));
+    }
+
+    /* For `--streaming-stats`: the summary cut down to what we still have,
+       with no responses left to go back over */
+    fn publish_streaming(&self) {
+        let statistics = self.statistics();
+        match &self.longest_document {
+            Some((_, document)) => print!("The following is the longest raw response body we received, which we take as representative:\n\n{:#?}\n\n", document),
+            None => println!("Could not display representative response body (no successful responses)")
+        };
+
+        println!("Method: {}", self.method());
+        println!("Number of requests: {}", statistics.total_requests);
+        if self.config.run_for.is_some() {
+            println!("Requests completed in {}: {}", self.format_duration(self.wall_time), statistics.total_requests);
+        }
+        println!(
+            "Percentage succeeded connecting: {}",
+            paint(format!("{}%", statistics.percentage_succeeded), success_rate_color(statistics.percentage_succeeded))
+        );
+        println!("Unique non-200 error codes encountered: {:#?}", statistics.non_200_status_codes);
+        println!(
+            "Responses by class: {}",
+            statistics.class_counts.iter().map(|(class, count)| format!("{} {}", class.label(), count)).join(", ")
+        );
+
+        let describe = |duration: Option<Duration>| duration.map_or(String::from("n/a"), |duration| self.format_duration(duration));
+        println!("Fastest response time: {}", describe(statistics.fastest));
+        println!("Mean response time: {}", describe(statistics.mean));
+        if let Some(streaming) = &self.streaming {
+            let streaming = streaming.lock().unwrap();
+            println!(
+                "Response time percentiles (estimated, from {} samples): {}",
+                streaming.count,
+                STREAMING_QUANTILES.iter().map(|p| format!("p{} {}", p, describe(streaming.quantile(*p)))).join(", ")
+            );
+        }
+        println!("Slowest response time: {}", describe(statistics.slowest));
+        match (statistics.smallest_size, statistics.largest_size) {
+            (Some(smallest), Some(largest)) => println!("Smallest size: {} B, largest size: {} B", smallest, largest),
+            _ => println!("No sizes recorded (no successful responses)"),
+        }
+
+        println!("Connection errors encountered, if any: {}", paint_errors(&self.failed_responses));
+    }
+
+    /* Every successful request's latency, in the order they completed - the
+       raw data behind `statistics`, for anyone wanting to run their own analysis */
+    pub fn samples(&self) -> Vec<Duration> {
+        return self.successful_responses.iter().map(|response| response.time_taken).collect();
+    }
+
+    /* Successful responses per second of wall-clock time */
+    pub fn throughput(&self) -> f64 {
+        return self.successful_responses.len() as f64 / self.wall_time.as_secs_f64();
+    }
+
+    /* Crunches the numbers for everything gathered so far */
+    pub fn statistics(&self) -> Statistics {
+        if let Some(streaming) = &self.streaming {
+            return Statistics::from_streaming(self.target, &streaming.lock().unwrap(), &self.failed_responses);
+        }
+
+        return Statistics::compute(self.target, &self.successful_responses, &self.failed_responses);
+    }
+
+    /* Prints request statistics out to terminal */
+    pub fn publish(&self) {
+        if let Some(local) = self.config.interface {
+            println!("Local address used: {}", local);
+        }
+
+        if self.config.handshake_only {
+            self.publish_handshakes();
+            return;
+        }
+        if self.streaming.is_some() {
+            self.publish_streaming();
+            return;
+        }
+
+        let statistics = self.statistics();
+
+        // 204s, HEADs and the like legitimately have nothing to show.
+        let all_empty = !self.successful_responses.is_empty() && self.successful_responses.iter().all(|i| i.body_size == 0);
+
+        match &self.longest_document {
+            Some(_) if all_empty && !self.config.include_headers_in_body => println!("All responses had empty bodies"),
+            Some((_, document)) =>  print!("The following is the longest raw response body we received, which we take as representative:\n\n{:#?}\n\n", document),
+            None => println!("Could not display representative response body (no successful responses)")
+        };
+
+        println!("Method: {}", self.method());
+        println!("Number of requests: {}", statistics.total_requests);
+        if self.config.run_for.is_some() {
+            println!("Requests completed in {}: {}", self.format_duration(self.wall_time), statistics.total_requests);
+        }
+        if let Some(min_samples) = self.config.min_samples.filter(|min_samples| self.successful_responses.len() < *min_samples) {
+            // a p99 from a handful of samples is just the slowest of them.
+            println!(
+                "{}",
+                paint(format!(
+                    "Only {} successful responses, fewer than the {} asked for with --min-samples: the statistics below are unreliable",
+                    self.successful_responses.len(), min_samples
+                ), Color::Yellow)
+            );
+        }
+        println!(
+            "Percentage succeeded connecting: {}",
+            paint(format!("{}%", statistics.percentage_succeeded), success_rate_color(statistics.percentage_succeeded))
+        );
+        // non-200s aren't necessarily wrong (redirects, say), so they only warn.
+        let non_200_color = if statistics.percentage_non_200 > 0.0 { Color::Yellow } else { Color::Green };
+        println!(
+            "Percentage of successful responses with non-200 response codes (includes redirects, etc.): {}",
+            paint(format!("{}%", statistics.percentage_non_200), non_200_color)
+        );
+
+        println!("Unique non-200 error codes encountered: {:#?}", statistics.non_200_status_codes);
+        println!(
+            "Responses by class: {}",
+            statistics.class_counts.iter().map(|(class, count)| format!("{} {}", class.label(), count)).join(", ")
+        );
+        println!(
+            "Responses by status: {}",
+            statistics.status_buckets.iter()
+                .filter(|(bucket, count)| **bucket != "other" || **count > 0)
+                .map(|(bucket, count)| format!(
+                    "{} {} ({:.1}%)", bucket, count, *count as f64 / statistics.successful_requests.max(1) as f64 * 100_f64
+                ))
+                .join(", ")
+        );
+        self.publish_http_versions();
+        match statistics.fastest {
+            Some(interval) => println!("Fastest response time: {}", self.format_duration(interval)),
+            None => println!("No fastest response time recorded (no successful responses)")
+        }
+        match (statistics.mean, statistics.mean_margin) {
+            (Some(interval), Some(margin)) if self.config.report_confidence => println!(
+                "Mean response time: {} ± {} (95% confidence, {} samples)",
+                self.format_duration(interval), self.format_duration(margin), statistics.successful_requests
+            ),
+            (Some(interval), None) if self.config.report_confidence => println!(
+                "Mean response time: {} (too few samples for a confidence interval)", self.format_duration(interval)
+            ),
+            (Some(interval), _) => println!("Mean response time: {}", self.format_duration(interval)),
+            (None, _) => println!("No mean response time recorded (no successful responses)")
+        }
+        if let Some(weighted) = size_weighted_mean(&self.successful_responses) {
+            println!("Size-weighted mean response time: {} (each response weighted by its body size)", self.format_duration(weighted));
+        }
+
+        match statistics.median {
+            Some(interval) => println!("Median response time: {}", self.format_duration(interval)),
+            None => println!("No median response time recorded (no successful responses)")
+        }
+
+        match statistics.slowest {
+            Some(interval) => println!("Slowest response time: {}", self.format_duration(interval)),
+            None => println!("No slowest response time recorded (no successful responses)")
+        }
+        self.publish_time_to_headers();
+
+        if !self.config.latency_buckets.is_empty() {
+            self.publish_latency_buckets(statistics.total_requests);
+        }
+
+        if let Some(threshold) = self.config.apdex_threshold {
+            self.publish_apdex(threshold, statistics.total_requests);
+        }
+
+        if self.config.head {
+            self.publish_header_sizes();
+        } else if all_empty {
+            println!("No sizes to report (all responses had empty bodies)");
+        } else {
+            match statistics.smallest_size {
+                Some(size) => println!("Smallest size: {:?} B", size),
+                None => println!("No smallest size recorded (no successful responses)")
+            }
+            match statistics.largest_size {
+                Some(size) => println!("Largest size: {:?} B", size),
+                None => println!("No largest size recorded (no successful responses)")
+            }
+
+            self.publish_sizes_by_content_type();
+        }
+
+        if let Some(chunk_size) = self.config.read_chunk_size {
+            self.publish_transfer_rates(chunk_size);
+        }
+
+        if self.config.body.is_some() {
+            self.publish_upload_rates();
+        }
+
+        if self.config.compare_cache {
+            self.publish_cache_comparison();
+        }
+
+        if self.config.concurrency > 1 {
+            println!(
+                "Throughput: {:.1} successful responses/s across {} concurrent connections",
+                self.throughput(), self.config.concurrency
+            );
+        }
+
+        if let Some(threshold) = self.config.slow_threshold {
+            self.publish_slow_responses(threshold);
+        }
+
+        self.publish_length_mismatches();
+        self.publish_trailers();
+
+        if self.config.expect_continue {
+            self.publish_continue_waits();
+        }
+
+        if self.config.proxy.is_some() && self.target.scheme() == "https" {
+            self.publish_tunnel_times();
+        }
+
+        if let Some(server) = self.config.dns_server {
+            self.publish_dns_times(server);
+        }
+
+        if self.config.hash_bodies {
+            self.publish_distinct_bodies(statistics.distinct_bodies);
+        }
+
+        if self.config.follow_redirects {
+            self.publish_redirect_chains();
+        }
+
+        if let Some(name) = &self.config.trace_header {
+            self.publish_slowest_traces(name);
+        }
+
+        if self.config.strict_parse {
+            let malformed = self.failed_responses.iter().filter(|failure| failure.is::<MalformedResponseError>()).count();
+            let color = if malformed > 0 { Color::Red } else { Color::Green };
+            println!("Responses that weren't HTTP (counted as failures): {}", paint(malformed, color));
+        }
+
+        if self.config.validate_json_body {
+            let invalid = self.failed_responses.iter().filter(|failure| failure.is::<InvalidJsonBodyError>()).count();
+            let color = if invalid > 0 { Color::Red } else { Color::Green };
+            println!("Responses whose body wasn't valid JSON (counted as failures): {}", paint(invalid, color));
+        }
+
+        if statistics.incomplete_responses > 0 {
+            println!(
+                "Incomplete responses (connection reset mid-read): {}",
+                paint(statistics.incomplete_responses, Color::Yellow)
+            );
+        }
+
+        if self.config.proxy.is_some() {
+            let refused = self.failed_responses.iter().filter(|failure| failure.is::<ProxyAuthError>()).count();
+            if refused > 0 {
+                println!("Proxy authentication failures (407 from the proxy): {}", paint(refused, Color::Red));
+            }
+        }
+
+        if self.config.retry_empty {
+            let abandoned = self.failed_responses.iter().filter(|failure| failure.is::<EmptyResponseError>()).count();
+            let color = if abandoned > 0 { Color::Red } else if self.empty_retries > 0 { Color::Yellow } else { Color::Green };
+            println!(
+                "Empty responses retried: {} ({} requests still empty after {} retries, counted as failures)",
+                paint(self.empty_retries, color), abandoned, self.config.retries
+            );
+        }
+
+        if !self.config.expected_headers.is_empty() {
+            self.publish_header_assertions();
+        }
+
+        if self.config.report_total_time {
+            self.publish_total_times();
+        }
+
+        self.publish_latency_by_server_address();
+
+        let (ipv6, ipv4) = self.family_wins.get();
+        if ipv6 + ipv4 > 0 {
+            println!("Connections won by address family (Happy Eyeballs): IPv6 {}, IPv4 {}", ipv6, ipv4);
+        }
+
+        if !self.config.user_agents.is_empty() {
+            self.publish_latency_by_user_agent();
+        }
+
+        if self.config.accepts.len() > 1 {
+            self.publish_content_negotiation();
+        }
+
+        if !self.config.weighted_paths.is_empty() {
+            self.publish_latency_by_path();
+        }
+
+        if self.config.report_deciles {
+            self.publish_success_by_decile();
+        }
+
+        if self.config.preflight_origin.is_some() {
+            self.publish_preflights(statistics.mean);
+        }
+
+        if let Some(idle) = self.config.idle_probe {
+            self.publish_idle_probe(idle);
+        }
+
+        if self.config.conditional_requests {
+            self.publish_conditional_requests();
+        }
+
+        if let Some(jitter) = self.config.jitter {
+            println!("Jitter: a random 0 to {} before each request", self.format_duration(jitter));
+        }
+
+        if let Some(limit) = self.config.max_total_bytes {
+            let bytes_read = self.bytes_read.load(Ordering::Relaxed);
+            let capped = bytes_read >= limit
+                && (self.config.run_for.is_some() || statistics.total_requests < self.config.number_of_requests as usize);
+            println!(
+                "Bytes downloaded: {} B of the --max-total-bytes {} B{}",
+                bytes_read, limit, if capped { ", so the run stopped early" } else { "" }
+            );
+        }
+
+        if let Some(limit) = self.config.abort_after_failures {
+            if self.failures_so_far.load(Ordering::Relaxed) >= limit {
+                println!("{}", paint(
+                    format!("Aborted early: {} requests failed, reaching the --abort-after-failures threshold of {}", statistics.failed_requests, limit),
+                    Color::Red,
+                ));
+            }
+        }
+
+        if self.uses_randomness() {
+            println!("Random seed: {} (replay this run with --seed {})", self.seed, self.seed);
+        }
+
+        if self.config.warm_pool.is_some() {
+            println!(
+                "Warm pool: {} connections opened in {} before timing began, so no measured request paid for connecting",
+                self.warm_connections, self.format_duration(self.warm_pool_time)
+            );
+        }
+
+        if self.config.keep_alive {
+            println!(
+                "Keep-alive reconnections: {} ({} spent reconnecting, {} forced by failed requests)",
+                self.reconnections, self.format_duration(self.reconnection_time), self.error_reconnections
+            );
+        }
+
+        println!("Connection errors encountered, if any: {}", paint_errors(&self.failed_responses));
 
-impl fmt::Display for NotReachableError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        return write!(f, "Could not connect to URL: no host was reachable");
     }
 }
 
-impl Error for NotReachableError {
-    fn description(&self) -> &str {
-        return "Could not connect to URL: no host was reachable";
+/* Whether `response` carries the header `expected` names, with the value it
+   names if it names one. */
+fn header_matches(response: &ResponseProperties, expected: &(String, Option<String>)) -> bool {
+    let (name, value) = expected;
+    return response.headers.iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .any(|(_, actual)| value.as_ref().is_none_or(|value| actual == value));
+}
+
+/* The request numbers to work through: `count` of them, or however many
+   get started before `deadline` if there is one. */
+fn request_indices(count: i64, deadline: Option<Instant>) -> impl Iterator<Item = i64> {
+    return (0..).take_while(move |&index| match deadline {
+        Some(deadline) => Instant::now() < deadline,
+        None => index < count,
+    });
+}
+
+/* Sums up how `actual` strays from `expected`: where the bytes first part
+   ways, and how many lines differ, starting from the first that does */
+fn describe_difference(expected: &str, actual: &str) -> String {
+    let first_byte = expected.bytes().zip(actual.bytes())
+        .position(|(a, b)| a != b)
+        .unwrap_or(expected.len().min(actual.len()));
+    let expected_lines: Vec<&str> = expected.lines().collect();
+    let actual_lines: Vec<&str> = actual.lines().collect();
+    let line_count = expected_lines.len().max(actual_lines.len());
+    let differing_lines = (0..line_count).filter(|&i| expected_lines.get(i) != actual_lines.get(i)).count();
+    let clip = |line: Option<&&str>| match line {
+        Some(line) if line.chars().count() > 80 => format!("{:?}...", line.chars().take(80).collect::<String>()),
+        Some(line) => format!("{:?}", line),
+        None => String::from("(no line)"),
+    };
+
+    let mut summary = format!(
+        "  expected {} B, got {} B, first differing at byte {}\n  {} of {} lines differ",
+        expected.len(), actual.len(), first_byte, differing_lines, line_count
+    );
+    if let Some(line) = (0..line_count).find(|&i| expected_lines.get(i) != actual_lines.get(i)) {
+        summary.push_str(&format!(
+            ", first at line {}:\n  - {}\n  + {}",
+            line + 1, clip(expected_lines.get(line)), clip(actual_lines.get(line))
+        ));
     }
+
+    return summary;
 }
 
-#[derive(Debug, Clone)]
-pub struct ResponseProperties {
-    pub time_taken: Duration,
-    pub status_code: i32,
-    pub document: String
+/* Green when everything succeeded, yellow when a few didn't, red past that */
+fn success_rate_color(percentage: f64) -> Color {
+    if percentage >= 100.0 {
+        return Color::Green;
+    }
+    if percentage >= 90.0 {
+        return Color::Yellow;
+    }
+
+    return Color::Red;
 }
 
-#[derive(Debug)]
-pub struct Profiler<'a> {
-    pub target: &'a Url,
-    pub number_of_requests: i64,
-    formatted_request: String,
-    pub successful_responses: Vec<ResponseProperties>,
-    pub failed_responses: Vec<Box<dyn Error>>,
+fn paint_errors(errors: &[Box<dyn Error + Send + Sync>]) -> String {
+    let listing = format!("{:?}", errors);
+    if errors.is_empty() {
+        return listing;
+    }
+
+    return paint(listing, Color::Red);
 }
 
-impl Profiler<'_> {
+/* Where a response ends, as far as its headers let us tell */
+enum Framing {
+    Incomplete,
+    Complete(usize),
+    UntilClose,
+}
 
-    pub fn new(target: &Url, number_of_requests: i64) -> Profiler {
-        return Profiler{
-            target: target, 
-            formatted_request: get_formatted_request(target),
-            number_of_requests: number_of_requests, 
-            successful_responses: Vec::new(),
-            failed_responses: Vec::new()
+fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    return haystack.windows(needle.len()).position(|window| window == needle);
+}
+
+/* Case-insensitively looks up a header's value in a raw response header block */
+fn find_header(head: &str, name: &str) -> Option<String> {
+    // skip the status line, then look for `Name: value`.
+    for line in head.split("\r\n").skip(1) {
+        if let Some((key, value)) = line.split_once(':') {
+            if key.trim().eq_ignore_ascii_case(name) {
+                return Some(value.trim().to_string());
+            }
         }
     }
 
-    fn fetch<T: Read + Write>(&self, connection: &mut T, content: &String) -> Result<ResponseProperties, Box<dyn Error>> {
+    return None;
+}
 
-        connection.write_all(content.as_bytes())?;
-        connection.flush()?;
+fn message_framing(buffer: &[u8], head_request: bool) -> Framing {
+    let header_end = match find_subsequence(buffer, b"\r\n\r\n") {
+        Some(position) => position + 4,
+        None => return Framing::Incomplete,
+    };
+    // a HEAD response's Content-Length describes the body a GET would have
+    // got, not anything that's coming our way.
+    if head_request {
+        return Framing::Complete(header_end);
+    }
+    let head = String::from_utf8_lossy(&buffer[..header_end]);
 
-        let mut read_buffer = Vec::new();
-        let before = Instant::now();
-        connection.read_to_end(&mut read_buffer)?;
-        let elapsed_time = Instant::now().duration_since(before);
+    // these never carry a body, whatever their headers claim.
+    let status = head.split_whitespace().nth(1).and_then(|code| code.parse::<i32>().ok());
+    if status == Some(204) || status == Some(304) {
+        return Framing::Complete(header_end);
+    }
 
-        let (code, page) = parse_status_code_and_page(&read_buffer);
+    let chunked = find_header(&head, "Transfer-Encoding")
+        .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+    if chunked {
+        return chunked_message_end(buffer, header_end);
+    }
 
-        return Ok(ResponseProperties{
-            document: page.clone(),
-            time_taken: elapsed_time,
-            status_code: code,
-        });
+    if let Some(length) = find_header(&head, "Content-Length").and_then(|v| v.parse::<usize>().ok()) {
+        if buffer.len() >= header_end + length {
+            return Framing::Complete(header_end + length);
+        }
+        return Framing::Incomplete;
     }
 
-    fn create_regular_connection(&self) -> Result<TcpStream, Box<dyn Error>> {
-        let socket_addresses = self.target.socket_addrs(|| None)?;
+    return Framing::UntilClose;
+}
 
-        // unlike TcpStream::connect, connect_timeout does not automatically
-        // try the next address in a sequence - hence why I'm wrapping it in a
-        // loop myself.
-        for address in socket_addresses.iter() {
-            match TcpStream::connect_timeout(&address, Duration::new(5, 0)) {
-                Ok(connection) => {
-                    connection.set_read_timeout(Some(Duration::new(3, 0)))?;
-                    connection.set_write_timeout(Some(Duration::new(3, 0)))?;
-                    return Ok(connection);
-                }
-                Err(e) => {
-                    println!("Error connecting to {}: {}", &address, e);
-                    continue;
-                }
+/* A chunked body with its framing taken out, and the trailer section that
+   followed the last chunk - or None if the chunks don't add up to a whole body */
+fn decode_chunked(body: &[u8]) -> Option<(Vec<u8>, &[u8])> {
+    let mut decoded = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let line_end = cursor + find_subsequence(&body[cursor..], b"\r\n")?;
+        let size_line = String::from_utf8_lossy(&body[cursor..line_end]);
+        let size = usize::from_str_radix(size_line.split(';').next().unwrap().trim(), 16).ok()?;
+        cursor = line_end + 2;
+        if size == 0 {
+            break;
+        }
+        decoded.extend_from_slice(body.get(cursor..cursor + size)?);
+        cursor += size + 2;
+        if cursor > body.len() {
+            return None;
+        }
+    }
+
+    return Some((decoded, &body[cursor..]));
+}
+
+/* Walks the chunk size lines of a chunked body to find where it finishes */
+fn chunked_message_end(buffer: &[u8], body_start: usize) -> Framing {
+    let mut cursor = body_start;
+    loop {
+        let line_end = match find_subsequence(&buffer[cursor..], b"\r\n") {
+            Some(position) => cursor + position,
+            None => return Framing::Incomplete,
+        };
+        let size_line = String::from_utf8_lossy(&buffer[cursor..line_end]);
+        // chunk extensions follow a `;`, and we don't care about them.
+        let size = match usize::from_str_radix(size_line.split(';').next().unwrap().trim(), 16) {
+            Ok(size) => size,
+            // can't make sense of the framing, so wait for the server to hang up.
+            Err(_) => return Framing::UntilClose,
+        };
+
+        if size == 0 {
+            // the last chunk is followed by optional trailers and a blank line.
+            return match find_subsequence(&buffer[line_end..], b"\r\n\r\n") {
+                Some(position) => Framing::Complete(line_end + position + 4),
+                None => Framing::Incomplete,
             };
         }
 
-        return Err(Box::new(NotReachableError));
+        cursor = line_end + 2 + size + 2;
+        if cursor > buffer.len() {
+            return Framing::Incomplete;
+        }
     }
+}
 
-    fn create_ssl_connection(&self) -> Result<SslStream<TcpStream>, Box<dyn Error>> {
-        let connector = SslConnector::builder(SslMethod::tls())?.build();
-        let stream = self.create_regular_connection()?;
-        let host = self.target.host_str().unwrap();
-        return Ok(connector.connect(host, stream)?);
+/* Whether the server has hung up on a connection we've left idle - checked
+   with a read that should find nothing to read, and give up almost at once */
+fn closed_while_idle<T: Read + ReadDeadline>(connection: &mut T) -> bool {
+    if connection.set_read_wait(Duration::from_millis(1)).is_err() {
+        return true;
     }
+    let mut byte = [0; 1];
+    let closed = match connection.read(&mut byte) {
+        // EOF, or something unasked for, which leaves the connection no use to us either.
+        Ok(_) => true,
+        Err(ref e) if is_timeout(e) => false,
+        Err(_) => true,
+    };
 
-    fn gather_http_site_statistics(&mut self) -> Result<(), Box<dyn Error>> {
+    return closed || connection.set_read_wait(SOCKET_TIMEOUT).is_err();
+}
 
-        for _ in 0..self.number_of_requests {
-            let mut connection = self.create_regular_connection()?;
-            match self.fetch(&mut connection, &self.formatted_request) {
-                Ok(statistic) => {
-                    self.successful_responses.push(statistic);
-                }
-                Err(x) => {
-                    self.failed_responses.push(x);
-                }
+fn is_timeout(e: &io::Error) -> bool {
+    // which of these a timed out read reports depends on the platform.
+    return e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut;
+}
+
+/* Waits up to `wait` for the first response head after an `Expect:
+   100-continue`, or None if nothing came in time.
+
+   This reads a byte at a time, so that nothing past the head is taken off
+   the connection: whatever follows is either a final response, or the start
+   of one still to be read. */
+fn read_interim<T: Read + ReadDeadline>(connection: &mut T, wait: Duration) -> Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>> {
+    connection.set_read_wait(wait)?;
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        match connection.read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => head.push(byte[0]),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(ref e) if is_timeout(e) && head.is_empty() => {
+                connection.set_read_wait(SOCKET_TIMEOUT)?;
+                return Ok(None);
             }
+            Err(e) => return Err(Box::new(e)),
         }
+    }
+    connection.set_read_wait(SOCKET_TIMEOUT)?;
 
-        return Ok(());
-    }    
+    return Ok(Some(head));
+}
 
-    fn gather_https_site_statistics(&mut self) -> Result<(), Box<dyn Error>> {
+/* Reads a single response off `connection`.
 
-        for _ in 0..self.number_of_requests {
-            let mut connection = self.create_ssl_connection()?;
-            match self.fetch(&mut connection, &self.formatted_request) {
-                Ok(statistic) => {
-                    self.successful_responses.push(statistic);
-                }
-                Err(x) => {
-                    self.failed_responses.push(x);
-                }
+   Without `framed` we read until the server closes the connection, which is
+   what `Connection: close` promises us. With it, we stop as soon as the
+   headers say the message is complete, since keep-alive servers will sit on
+   an open connection waiting for the next request.
+
+   The socket read timeout only bounds a single read, so a server dripping
+   out a byte at a time could otherwise keep us here forever - `deadline`
+   caps the response as a whole.
+
+   A connection reset once we've had some of the response isn't thrown away:
+   what did arrive is returned, with `true` alongside to say it's partial.
+
+   `read_buffer` holds anything of the response that's already been read. */
+fn read_response<T: Read + ReadDeadline>(
+    connection: &mut T, mut read_buffer: Vec<u8>, max_size: usize, chunk_size: usize, framed: bool, head_request: bool, deadline: Option<Instant>
+) -> Result<(Vec<u8>, bool, Arrivals), Box<dyn Error + Send + Sync>> {
+    // read in chunks rather than using `read_to_end`, so we can bail out
+    // as soon as a response grows past `max_size` - and see when each arrived.
+    let mut chunk = vec![0; chunk_size];
+    let mut arrivals = Arrivals::default();
+    // a head that came back in place of `100 Continue` is already all here.
+    if find_subsequence(&read_buffer, b"\r\n\r\n").is_some() {
+        arrivals.headers_complete = Some(Instant::now());
+    }
+    loop {
+        // there's no waiting on a close for a HEAD response - it's done at the headers.
+        if framed || head_request {
+            if let Framing::Complete(end) = message_framing(&read_buffer, head_request) {
+                read_buffer.truncate(end);
+                return Ok((read_buffer, false, arrivals));
             }
         }
 
-        return Ok(());
+        if let Some(deadline) = deadline {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Box::new(RequestTimeoutError));
+            }
+            connection.set_read_wait(SOCKET_TIMEOUT.min(deadline - now))?;
+        }
+
+        let bytes_read = match connection.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(ref e) if is_timeout(e) && deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                return Err(Box::new(RequestTimeoutError));
+            }
+            Err(ref e) if e.kind() == ErrorKind::ConnectionReset && !read_buffer.is_empty() => {
+                warn!("Connection reset after {} B of the response, keeping what arrived", read_buffer.len());
+                return Ok((read_buffer, true, arrivals));
+            }
+            Err(e) => return Err(Box::new(e)),
+        };
+        read_buffer.extend_from_slice(&chunk[..bytes_read]);
+        arrivals.record(bytes_read);
+        if arrivals.headers_complete.is_none() {
+            // the terminator could straddle this read and the last one.
+            let from = read_buffer.len().saturating_sub(bytes_read + 3);
+            if find_subsequence(&read_buffer[from..], b"\r\n\r\n").is_some() {
+                arrivals.headers_complete = Some(Instant::now());
+            }
+        }
+        if read_buffer.len() > max_size {
+            return Err(Box::new(ResponseTooLargeError{ limit: max_size }));
+        }
     }
 
-    /* Main entrypoint to `Profiler` */
-    pub fn profile(&mut self) {
-        if self.target.scheme() == "https" {
-            if let Err(x) = self.gather_https_site_statistics() {
-                println!("Encountered unfixable error creating HTTPS connection: {:?}", x);
-                process::exit(1);
-            };
-        } else {
-            if let Err(y) = self.gather_http_site_statistics() {
-                println!("Encountered unfixable error creating HTTP connection: {:?}", y);
-                process::exit(1);
-            };
+    if framed {
+        if let Framing::Incomplete = message_framing(&read_buffer, head_request) {
+            return Err(Box::new(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "Connection closed before the response was complete",
+            )));
         }
     }
 
-    /* Prints request statistics out to terminal */
-    pub fn publish(&self) {
-        let total_requests = self.successful_responses.len() + self.failed_responses.len();
-        let percentage_succeeded = self.successful_responses.len() as f64 / total_requests as f64;
+    return Ok((read_buffer, false, arrivals));
+}
+
+/* When the reads of a response completed, as far as working out how fast
+   it came in and whether it stalled partway */
+#[derive(Debug, Default)]
+struct Arrivals {
+    first: Option<(Instant, usize)>,
+    last: Option<Instant>,
+    // every byte but the first read's.
+    bytes_after_first: usize,
+    longest_gap: Option<Duration>,
+    // when the read that finished the headers came in.
+    headers_complete: Option<Instant>,
+}
 
-        let unsuccessful_status_codes:Vec<i32> = self.successful_responses.iter()
-            .filter(|&i| i.status_code != 200).map(|i| i.status_code).collect();
+impl Arrivals {
+    fn record(&mut self, bytes: usize) {
+        let now = Instant::now();
+        match (self.first, self.last) {
+            (None, _) => self.first = Some((now, bytes)),
+            (Some((first, _)), last) => {
+                let gap = now.duration_since(last.unwrap_or(first));
+                self.longest_gap = Some(self.longest_gap.map_or(gap, |longest| longest.max(gap)));
+                self.bytes_after_first += bytes;
+            }
+        }
+        self.last = Some(now);
+    }
 
-        let durations:Vec<Duration> = self.successful_responses.iter().map(|i| i.time_taken).collect();
-        let mean = durations.iter().sum::<Duration>().checked_div(durations.len() as u32);
-        let sorted_durations = durations.iter().cloned().sorted().collect::<Vec<Duration>>();
+    fn transfer_rate(&self) -> Option<f64> {
+        let ((first, _), last) = (self.first?, self.last?);
+        let elapsed = last.duration_since(first).as_secs_f64();
+        if self.bytes_after_first == 0 || elapsed == 0.0 {
+            return None;
+        }
 
-        let sizes:Vec<usize> = self.successful_responses.iter().map(|i| i.document.len()).collect();
+        return Some(self.bytes_after_first as f64 / elapsed);
+    }
+}
 
-        match self.successful_responses.iter().max_by_key(|i| i.document.len()) {
-            Some(response) =>  print!("The following is the longest raw response body we received, which we take as representative:\n\n{:#?}\n\n", response.document),
-            None => println!("Could not display representative response body (no successful responses)")
-        };
+/* Splits the header block of a response into name/value pairs */
+fn parse_headers(head: &str) -> Vec<(String, String)> {
+    return parse_header_lines(head.split_once("\r\n").map_or("", |(_, lines)| lines));
+}
 
-        println!("Number of requests: {}", total_requests);
-        println!(
-            "Percentage succeeded connecting: {}%",
-            percentage_succeeded * 100 as f64
-        );
-        println!(
-            "Percentage of successful responses with non-200 response codes (includes redirects, etc.): {}%",
-            ((unsuccessful_status_codes.len() as f64) / (self.successful_responses.len() as f64)) * (100 as f64)
-        );
+/* Name/value pairs from header lines, up to the blank line that ends them -
+   the trailers of a chunked body included */
+fn parse_header_lines(lines: &str) -> Vec<(String, String)> {
+    return lines.split("\r\n")
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+}
 
-        println!("Unique non-200 error codes encountered: {:#?}", unsuccessful_status_codes.iter().cloned().collect::<HashSet<i32>>());
-        match durations.iter().min() {
-            Some(interval) => println!("Fastest response time: {:?}", interval),
-            None => println!("No fastest response time recorded (no successful responses)")
-        }
-        match mean {
-            Some(interval) => println!("Mean response time: {:?}", interval),
-            None => println!("No mean response time recorded (no successful responses)")
-        }
+/* Turns the raw bytes of a response into what we record about it */
+fn parse_response(read_buffer: &[u8], elapsed_time: Duration, head_request: bool) -> ResponseProperties {
+    let (code, page) = parse_status_code_and_page(read_buffer);
+    let head_end = find_subsequence(read_buffer, b"\r\n\r\n").unwrap_or(read_buffer.len());
+    let head = String::from_utf8_lossy(&read_buffer[..head_end]);
 
-        match sorted_durations.len() {
-            0 => println!("No mean response time recorded (no successful responses)"),
-            1 => println!("Median response time: {:?}", sorted_durations[0]),
-            x => {
-                let median;
-                if x % 2 == 0 {
-                    median = sorted_durations[x / 2];
-                } else {
-                    median = (sorted_durations[x / 2] + sorted_durations[(x + 1) / 2]).checked_div(2).unwrap();
-                }
-                println!("Median response time: {:?}", median);
-            }
-        }
+    // `Keep-Alive: timeout=5, max=100` - we only care about `max`.
+    let keep_alive_max = find_header(&head, "Keep-Alive").and_then(|value| {
+        value.split(',')
+            .filter_map(|parameter| parameter.trim().strip_prefix("max=").map(|v| v.to_string()))
+            .next()
+            .and_then(|max| max.trim().parse::<u64>().ok())
+    });
+    let connection_has = |wanted: &str| find_header(&head, "Connection")
+        .is_some_and(|value| value.split(',').any(|token| token.trim().eq_ignore_ascii_case(wanted)));
+    // HTTP/1.0 connections close after every response unless the server
+    // explicitly offers otherwise, and a body that ran until the server hung
+    // up leaves nothing to reuse whatever the headers said.
+    let closes_connection = if head.starts_with("HTTP/1.0") { !connection_has("keep-alive") } else { connection_has("close") }
+        || matches!(message_framing(read_buffer, head_request), Framing::UntilClose);
 
-        match durations.iter().max() {
-            Some(interval) => println!("Slowest response time: {:?}", interval),
-            None => println!("No slowest response time recorded (no successful responses)")
-        }
+    // a chunked body's length is in the chunks, whatever Content-Length says,
+    // and these statuses never have a body to measure.
+    let chunked = find_header(&head, "Transfer-Encoding")
+        .is_some_and(|value| value.to_ascii_lowercase().contains("chunked"));
+    let bodiless = head_request || code == 204 || code == 304;
+    let declared_length = find_header(&head, "Content-Length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .filter(|_| !chunked && !bodiless);
 
-        match sizes.iter().min() {
-            Some(size) => println!("Smallest size: {:?} B", size),
-            None => println!("No smallest size recorded (no successful responses)")
-        }
-        match sizes.iter().max() {
-            Some(size) => println!("Largest size: {:?} B", size),
-            None => println!("No largest size recorded (no successful responses)")
+    // a chunked body is measured and shown without its framing, and any
+    // trailers after the last chunk count as headers. Chunks that don't add
+    // up, say from a reset partway, are left as they came.
+    let raw_body = &read_buffer[(head_end + 4).min(read_buffer.len())..];
+    let mut headers = parse_headers(&head);
+    let (page, body_hash, trailers) = match decode_chunked(raw_body).filter(|_| chunked && !bodiless) {
+        Some((decoded, trailer_section)) => {
+            let trailers = parse_header_lines(&String::from_utf8_lossy(trailer_section));
+            headers.extend(trailers.iter().cloned());
+            (String::from_utf8_lossy(&decoded).to_string(), fnv1a(&decoded), trailers)
         }
+        None => (page, fnv1a(raw_body), Vec::new()),
+    };
 
-        println!("Connection errors encountered, if any: {:?}", self.failed_responses);
+    return ResponseProperties{
+        body_size: page.len(),
+        body_hash,
+        header_size: (head_end + 4).min(read_buffer.len()),
+        document: page,
+        headers,
+        trailers,
+        time_taken: elapsed_time,
+        // callers that know about connection setup widen this themselves.
+        total_time: elapsed_time,
+        started_at: None,
+        status_code: code,
+        classification: StatusClass::from_status(code),
+        keep_alive_max,
+        closes_connection,
+        declared_length,
+        received_length: read_buffer.len().saturating_sub(head_end + 4),
+        user_agent: None,
+        accept: None,
+        path: None,
+        server_address: None,
+        incomplete: false,
+        continue_wait: None,
+        tunnel_time: None,
+        dns_time: None,
+        redirect_chain: Vec::new(),
+        trace_id: None,
+        transfer_rate: None,
+        longest_stall: None,
+        time_to_headers: None,
+        upload_rate: None,
+        http_version: head.split(' ').next().filter(|version| version.starts_with("HTTP/")).map(String::from),
+    };
+}
 
+/* 64-bit FNV-1a: fast, and plenty to tell bodies apart, though no use against
+   anyone trying to collide it */
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
     }
+
+    return hash;
 }
 
 /* Returns status code and just the response body for our perusal */
-fn parse_status_code_and_page(source: &Vec<u8>) -> (i32, String) {
+fn parse_status_code_and_page(source: &[u8]) -> (i32, String) {
     let text = String::from_utf8_lossy(source);
 
-    if text.len() == 0 {
+    if text.is_empty() {
         return (0, text.to_string());
     }
 
     // extract the status code using a regex - this is okay since I 
-    // don't want to capture the response headers anyway.
-    let re = Regex::new(r"^HTTP/1.1 (?P<status_code>.*?) ").unwrap();
-    let captures = re.captures(&text).unwrap();
-    let status_code: i32 = match captures.name("status_code") {
+    // don't want to capture the response headers anyway. Any HTTP/1.x
+    // version will do, and something that isn't a status line at all gets 0.
+    let re = Regex::new(r"^HTTP/1\.\d (?P<status_code>\d{3})").unwrap();
+    let status_code: i32 = match re.captures(&text).and_then(|captures| captures.name("status_code")) {
         Some(code) => code.as_str().parse::<i32>().map_or(0, |x| x),
         None => 0,
     };
@@ -247,11 +3352,230 @@ fn parse_status_code_and_page(source: &Vec<u8>) -> (i32, String) {
     return (status_code, content.to_string());
 }
 
-fn get_formatted_request(target: &Url) -> String {
-    let formatted_request = format!(
-        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: curl/7.58.0\r\nAccept: */*\r\nConnection: close\r\n\r\n",
-        target.as_str(), target.host_str().unwrap()
-    );
+/* Builds the origin-form target for a request line: the path plus any query
+   string, e.g. `/search?q=rust` for `http://host/search?q=rust#frag`. The
+   fragment is only ever meaningful to the client, so it's never sent. */
+fn origin_form_target(target: &Url) -> String {
+    let path = if target.path().is_empty() { "/" } else { target.path() };
+    return match target.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_string(),
+    };
+}
 
-    return String::from(formatted_request);
-}
\ No newline at end of file
+/* What goes between the method and the version in our request lines */
+fn request_target(target: &Url, absolute_form: bool) -> String {
+    if !absolute_form {
+        return origin_form_target(target);
+    }
+
+    let mut absolute = target.clone();
+    absolute.set_fragment(None);
+    return absolute.to_string();
+}
+
+/* Builds the raw bytes of the request we send.
+
+   Kept as bytes rather than a `String` so a body read from `--data-file` goes
+   out exactly as it was on disk, binary or not. */
+fn get_formatted_request(
+    target: &Url, method: &str, keep_alive: bool, body: Option<&[u8]>, absolute_form: bool, headers: &[(String, String)]
+) -> Vec<u8> {
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    let request_target = request_target(target, absolute_form);
+    let content_length = match body {
+        Some(bytes) => format!("Content-Length: {}\r\n", bytes.len()),
+        None => String::new(),
+    };
+    let headers: String = headers.iter().map(|(key, value)| format!("{}: {}\r\n", key, value)).collect();
+    let mut formatted_request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\n{}{}Connection: {}\r\n\r\n",
+        method, request_target, target.host_str().unwrap(), headers, content_length, connection
+    ).into_bytes();
+
+    if let Some(bytes) = body {
+        formatted_request.extend_from_slice(bytes);
+    }
+
+    return formatted_request;
+}
+
+/* A rate in bytes a second, in whichever of B/s, KB/s and MB/s reads best */
+fn format_rate(bytes_per_second: f64) -> String {
+    if bytes_per_second >= 1_000_000.0 {
+        return format!("{:.1} MB/s", bytes_per_second / 1_000_000.0);
+    }
+    if bytes_per_second >= 1_000.0 {
+        return format!("{:.1} KB/s", bytes_per_second / 1_000.0);
+    }
+
+    return format!("{:.0} B/s", bytes_per_second);
+}
+
+/* Writes out all of `bytes`, and says how long that took */
+fn timed_write<T: Write>(connection: &mut T, bytes: &[u8]) -> io::Result<Duration> {
+    let before = Instant::now();
+    connection.write_all(bytes)?;
+    connection.flush()?;
+
+    return Ok(Instant::now().duration_since(before));
+}
+
+/* What every TLS connection is made with: the system's trusted CAs, plus
+   those in `cacert` for verifying servers signed by a private CA */
+pub fn tls_connector(cacert: Option<&str>) -> Result<SslConnector, ErrorStack> {
+    let mut builder = SslConnector::builder(SslMethod::tls())?;
+    if let Some(path) = cacert {
+        builder.set_ca_file(path)?;
+    }
+
+    return Ok(builder.build());
+}
+
+/* `addresses` with the families taking turns, the first family first */
+fn interleave_families(addresses: &[SocketAddr]) -> Vec<SocketAddr> {
+    let first_is_ipv4 = addresses[0].is_ipv4();
+    let (mut first, mut second): (VecDeque<SocketAddr>, VecDeque<SocketAddr>) =
+        addresses.iter().partition(|address| address.is_ipv4() == first_is_ipv4);
+
+    let mut ordered = Vec::with_capacity(addresses.len());
+    while !first.is_empty() || !second.is_empty() {
+        ordered.extend(first.pop_front());
+        ordered.extend(second.pop_front());
+    }
+
+    return ordered;
+}
+
+// numbers trace IDs across every worker, so no two requests share one.
+static TRACE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/* A fresh ID for `--trace-header` - in W3C `traceparent` form if that's the
+   header it's going in, as plain hex otherwise. Unique rather than random:
+   the time, our process and a counter between them. */
+fn new_trace_id(header: &str) -> String {
+    let nanoseconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_nanos() as u64).unwrap_or(0);
+    let count = TRACE_COUNTER.fetch_add(1, Ordering::Relaxed) as u32;
+    let trace = format!("{:016x}{:08x}{:08x}", nanoseconds, process::id(), count);
+    if header.eq_ignore_ascii_case("traceparent") {
+        return format!("00-{}-{:08x}{:08x}-01", trace, process::id(), count);
+    }
+
+    return trace;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(text: &str) -> Url {
+        return Url::parse(text).unwrap();
+    }
+
+    #[test]
+    fn origin_form_keeps_the_query_and_drops_the_fragment() {
+        assert_eq!(origin_form_target(&url("http://h/search?q=rust#frag")), "/search?q=rust");
+    }
+
+    #[test]
+    fn origin_form_of_an_empty_path_is_the_root() {
+        assert_eq!(origin_form_target(&url("http://h")), "/");
+    }
+
+    #[test]
+    fn origin_form_of_a_fragment_only_url_drops_the_fragment() {
+        assert_eq!(origin_form_target(&url("http://h#frag")), "/");
+    }
+
+    #[test]
+    fn proxied_requests_use_absolute_form() {
+        assert_eq!(request_target(&url("http://h/search?q=rust#frag"), true), "http://h/search?q=rust");
+        assert_eq!(request_target(&url("http://h/search?q=rust#frag"), false), "/search?q=rust");
+    }
+
+    #[test]
+    fn request_line_has_the_path_and_query_then_the_host() {
+        let request = get_formatted_request(&url("http://h/a/b?x=1"), "GET", false, None, false, &[]);
+        let request = String::from_utf8(request).unwrap();
+        let mut lines = request.split("\r\n");
+
+        assert_eq!(lines.next(), Some("GET /a/b?x=1 HTTP/1.1"));
+        assert_eq!(lines.next(), Some("Host: h"));
+    }
+
+    /* A server on a port of its own that reads each request's head and
+       answers it with `respond`, given the request line. Gives back where it
+       is, and a handle that counts the connections it took once there have
+       been `connections` of them. */
+    fn mock_server<F>(connections: usize, respond: F) -> (Url, thread::JoinHandle<usize>)
+    where
+        F: Fn(&str, &mut TcpStream) + Send + 'static,
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            for _ in 0..connections {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut head = Vec::new();
+                let mut byte = [0u8; 1];
+                while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+                    head.push(byte[0]);
+                }
+                let head = String::from_utf8_lossy(&head);
+                respond(head.lines().next().unwrap_or(""), &mut stream);
+            }
+            return connections;
+        });
+
+        return (Url::parse(&format!("http://{}/", address)).unwrap(), handle);
+    }
+
+    #[test]
+    fn http_1_0_body_is_read_to_close_and_the_connection_is_not_reused() {
+        const BODY: &str = "the first half, and the second half";
+        // no Content-Length, no chunking - just the close to say it's over,
+        // with the body in two pieces so the first read can't get it all.
+        let (target, server) = mock_server(2, |_, stream| {
+            stream.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nthe first half").unwrap();
+            stream.flush().unwrap();
+            thread::sleep(Duration::from_millis(50));
+            stream.write_all(b", and the second half").unwrap();
+        });
+
+        let mut profiler = Profiler::with_config(&target, ProfilerConfig::new(2).keep_alive(true));
+        profiler.profile();
+
+        assert_eq!(server.join().unwrap(), 2);
+        assert!(profiler.failed_responses.is_empty());
+        assert_eq!(profiler.error_reconnections, 0);
+        assert_eq!(profiler.successful_responses.len(), 2);
+        for response in profiler.successful_responses.iter() {
+            assert_eq!(response.status_code, 200);
+            assert_eq!(response.http_version.as_deref(), Some("HTTP/1.0"));
+            assert_eq!(response.body_size, BODY.len());
+            assert!(response.closes_connection);
+        }
+        // the bodies themselves are set aside as they're recorded.
+        assert_eq!(profiler.longest_document.as_ref().map(|(_, body)| body.as_str()), Some(BODY));
+    }
+
+    #[test]
+    fn redirects_bouncing_between_two_urls_are_a_loop() {
+        // /a, then /b, and then back to /a is where it has to stop.
+        let (target, server) = mock_server(2, |request_line, stream| {
+            let location = if request_line.starts_with("GET /a ") { "/b" } else { "/a" };
+            let response = format!("HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", location);
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+        let target = target.join("/a").unwrap();
+
+        let mut profiler = Profiler::with_config(&target, ProfilerConfig::new(1).follow_redirects(true));
+        profiler.profile();
+
+        assert_eq!(server.join().unwrap(), 2);
+        assert!(profiler.successful_responses.is_empty());
+        assert_eq!(profiler.failed_responses.len(), 1);
+        assert!(profiler.failed_responses[0].is::<RedirectLoopError>());
+        assert!(!profiler.failed_responses[0].is::<TooManyRedirectsError>());
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/* One cookie, as a line of a Netscape-format cookies file has it */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub domain: String,
+    pub include_subdomains: bool,
+    pub path: String,
+    pub secure: bool,
+    // only kept so saving the jar doesn't lose it - we're not a browser.
+    pub http_only: bool,
+    // seconds since the epoch, or 0 for a session cookie.
+    pub expires: u64,
+    pub name: String,
+    pub value: String,
+}
+
+impl Cookie {
+    fn matches(&self, target: &Url, now: u64) -> bool {
+        let host = target.host_str().unwrap_or("").to_ascii_lowercase();
+        let domain_matches = host == self.domain
+            || (self.include_subdomains && host.ends_with(&format!(".{}", self.domain)));
+
+        return domain_matches
+            && path_matches(target.path(), &self.path)
+            && (!self.secure || target.scheme() == "https")
+            && (self.expires == 0 || self.expires > now);
+    }
+}
+
+/* RFC 6265's path-match: `/api` covers `/api` and `/api/v1`, but not `/apiary` */
+fn path_matches(request_path: &str, cookie_path: &str) -> bool {
+    return match request_path.strip_prefix(cookie_path) {
+        Some(rest) => rest.is_empty() || cookie_path.ends_with('/') || rest.starts_with('/'),
+        None => false,
+    };
+}
+
+/* Cookies to send with every request: those given with `--cookie`, which go
+   to whatever we're profiling, and those from a `--cookie-jar` file or picked
+   up from `Set-Cookie` along the way, which only go where they belong. */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CookieJar {
+    pub fixed: Vec<(String, String)>,
+    pub cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    /* Reads a Netscape-format cookies file, as written by curl or browser extensions */
+    pub fn load(path: &str) -> io::Result<CookieJar> {
+        let mut jar = CookieJar::default();
+        for line in fs::read_to_string(path)?.lines() {
+            // curl marks HttpOnly cookies with a prefix on what's otherwise a comment.
+            let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+                Some(rest) => (rest, true),
+                None => (line, false),
+            };
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() != 7 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("expected 7 tab-separated fields, found {} in {:?}", fields.len(), line),
+                ));
+            }
+            jar.cookies.push(Cookie{
+                domain: fields[0].trim_start_matches('.').to_ascii_lowercase(),
+                include_subdomains: fields[1].eq_ignore_ascii_case("TRUE"),
+                path: fields[2].to_string(),
+                secure: fields[3].eq_ignore_ascii_case("TRUE"),
+                http_only,
+                expires: fields[4].parse::<u64>().unwrap_or(0),
+                name: fields[5].to_string(),
+                value: fields[6].to_string(),
+            });
+        }
+
+        return Ok(jar);
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let mut contents = String::from("# Netscape HTTP Cookie File\n");
+        for cookie in self.cookies.iter() {
+            let domain = if cookie.include_subdomains { format!(".{}", cookie.domain) } else { cookie.domain.clone() };
+            contents.push_str(&format!(
+                "{}{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                if cookie.http_only { "#HttpOnly_" } else { "" },
+                domain,
+                if cookie.include_subdomains { "TRUE" } else { "FALSE" },
+                cookie.path,
+                if cookie.secure { "TRUE" } else { "FALSE" },
+                cookie.expires,
+                cookie.name,
+                cookie.value
+            ));
+        }
+
+        return fs::write(path, contents);
+    }
+
+    /* Adds `name=value; name2=value2`, as given to `--cookie` */
+    pub fn add_fixed(&mut self, raw: &str) -> Result<(), String> {
+        for pair in raw.split(';').map(|pair| pair.trim()).filter(|pair| !pair.is_empty()) {
+            match pair.split_once('=') {
+                Some((name, value)) if !name.trim().is_empty() => {
+                    self.fixed.push((name.trim().to_string(), value.trim().to_string()));
+                }
+                _ => return Err(format!("{:?} is not of the form name=value", pair)),
+            }
+        }
+
+        return Ok(());
+    }
+
+    /* The value of the `Cookie` header to send to `target`, if any */
+    pub fn header_for(&self, target: &Url) -> Option<String> {
+        let now = now();
+        let pairs: Vec<String> = self.fixed.iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .chain(self.cookies.iter()
+                .filter(|cookie| cookie.matches(target, now))
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value)))
+            .collect();
+        if pairs.is_empty() {
+            return None;
+        }
+
+        return Some(pairs.join("; "));
+    }
+
+    /* Takes in a `Set-Cookie` header that came back from `target`, and says
+       whether the jar changed as a result.
+
+       Only `Max-Age` is understood for expiry - `Expires` dates are ignored,
+       which leaves those cookies living for the rest of the session. */
+    pub fn store(&mut self, target: &Url, set_cookie: &str) -> bool {
+        let mut attributes = set_cookie.split(';').map(|attribute| attribute.trim());
+        let (name, value) = match attributes.next().and_then(|pair| pair.split_once('=')) {
+            Some((name, value)) if !name.trim().is_empty() => (name.trim().to_string(), value.trim().to_string()),
+            _ => return false,
+        };
+
+        let mut cookie = Cookie{
+            domain: target.host_str().unwrap_or("").to_ascii_lowercase(),
+            include_subdomains: false,
+            path: String::from("/"),
+            secure: false,
+            http_only: false,
+            expires: 0,
+            name,
+            value,
+        };
+        let mut expired = false;
+        for attribute in attributes {
+            let (key, argument) = attribute.split_once('=').unwrap_or((attribute, ""));
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" if !argument.trim().is_empty() => {
+                    cookie.domain = argument.trim().trim_start_matches('.').to_ascii_lowercase();
+                    cookie.include_subdomains = true;
+                }
+                "path" if argument.starts_with('/') => cookie.path = argument.to_string(),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "max-age" => match argument.trim().parse::<i64>() {
+                    Ok(seconds) if seconds <= 0 => expired = true,
+                    Ok(seconds) => cookie.expires = now() + seconds as u64,
+                    Err(_) => {}
+                },
+                _ => {}
+            }
+        }
+
+        let existing = self.cookies.iter().position(|other| {
+            other.name == cookie.name && other.domain == cookie.domain && other.path == cookie.path
+        });
+        return match (existing, expired) {
+            (Some(index), true) => {
+                self.cookies.remove(index);
+                true
+            }
+            (None, true) => false,
+            (Some(index), false) if self.cookies[index] == cookie => false,
+            (Some(index), false) => {
+                self.cookies[index] = cookie;
+                true
+            }
+            (None, false) => {
+                self.cookies.push(cookie);
+                true
+            }
+        };
+    }
+}
+
+fn now() -> u64 {
+    return SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_cookie_path_only_matches_on_a_segment_boundary() {
+        assert!(path_matches("/api", "/api"));
+        assert!(path_matches("/api/v1", "/api"));
+        assert!(path_matches("/api/v1", "/api/"));
+        assert!(path_matches("/anything", "/"));
+        assert!(!path_matches("/apiary", "/api"));
+        assert!(!path_matches("/", "/api"));
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::connect::{format_duration, TimeUnit};
+
+// how many of the latest latencies the sparkline covers.
+const SPARKLINE_WIDTH: usize = 40;
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// a terminal can take redrawing this often without flickering; a log
+// being written to can't take a line any more often than once a second.
+const REDRAW_EVERY: Duration = Duration::from_millis(100);
+const LINE_EVERY: Duration = Duration::from_secs(1);
+
+/* For `--dashboard`: a running tally of the requests so far, shared between
+   every worker, and drawn to stdout as they complete.
+
+   On a terminal that's a panel redrawn in place; anywhere else, e.g. piped
+   into a file, it's a plain line every so often instead, as escape codes
+   there would only be noise. */
+#[derive(Debug)]
+pub struct Dashboard {
+    completed: AtomicUsize,
+    succeeded: AtomicUsize,
+    // nanoseconds, summed over every success, for the running mean.
+    total_latency: AtomicU64,
+    recent: Mutex<VecDeque<Duration>>,
+    unit: Option<TimeUnit>,
+    in_place: bool,
+    // when we last drew, and how many lines are on screen to draw over.
+    drawn: Mutex<(Option<Instant>, usize)>,
+}
+
+impl Dashboard {
+    pub fn new(unit: Option<TimeUnit>) -> Dashboard {
+        return Dashboard{
+            completed: AtomicUsize::new(0),
+            succeeded: AtomicUsize::new(0),
+            total_latency: AtomicU64::new(0),
+            recent: Mutex::new(VecDeque::with_capacity(SPARKLINE_WIDTH)),
+            unit,
+            in_place: atty::is(atty::Stream::Stdout),
+            drawn: Mutex::new((None, 0)),
+        };
+    }
+
+    /* Counts a completed request - with its latency if it succeeded - and
+       redraws, if it's been long enough since the last time */
+    pub fn record(&self, latency: Option<Duration>) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        if let Some(latency) = latency {
+            self.succeeded.fetch_add(1, Ordering::Relaxed);
+            self.total_latency.fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+            let mut recent = self.recent.lock().unwrap();
+            if recent.len() == SPARKLINE_WIDTH {
+                recent.pop_front();
+            }
+            recent.push_back(latency);
+        }
+        self.draw(false);
+    }
+
+    /* Draws the final tally, however recently the last one was drawn */
+    pub fn finish(&self) {
+        self.draw(true);
+    }
+
+    fn draw(&self, force: bool) {
+        // another worker drawing right now is as good as us doing it.
+        let mut drawn = match force {
+            true => self.drawn.lock().unwrap(),
+            false => match self.drawn.try_lock() {
+                Ok(drawn) => drawn,
+                Err(_) => return,
+            },
+        };
+        let every = if self.in_place { REDRAW_EVERY } else { LINE_EVERY };
+        if !force && drawn.0.is_some_and(|last| last.elapsed() < every) {
+            return;
+        }
+
+        let lines = self.lines();
+        let mut output = String::new();
+        if self.in_place {
+            // back up over the last panel, and wipe each line as it's redrawn.
+            if drawn.1 > 0 {
+                output.push_str(&format!("\x1b[{}A", drawn.1));
+            }
+            for line in lines.iter() {
+                output.push_str(&format!("\x1b[2K{}\n", line));
+            }
+        } else {
+            output.push_str(&format!("{}\n", lines[..3].join(", ")));
+        }
+
+        // stdout going away is no reason to stop profiling.
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        let _ = handle.write_all(output.as_bytes());
+        let _ = handle.flush();
+        *drawn = (Some(Instant::now()), if self.in_place { lines.len() } else { 0 });
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let completed = self.completed.load(Ordering::Relaxed);
+        let succeeded = self.succeeded.load(Ordering::Relaxed);
+        let success_rate = if completed == 0 { 0.0 } else { succeeded as f64 / completed as f64 * 100.0 };
+        let mean = match succeeded {
+            0 => String::from("n/a"),
+            _ => format_duration(self.unit, Duration::from_nanos(self.total_latency.load(Ordering::Relaxed) / succeeded as u64)),
+        };
+
+        return vec![
+            format!("Requests: {}", completed),
+            format!("Succeeded: {:.1}%", success_rate),
+            format!("Mean: {}", mean),
+            format!("Recent: {}", sparkline(&self.recent.lock().unwrap())),
+        ];
+    }
+}
+
+/* One character per latency, from shortest to tallest between the fastest and slowest shown */
+fn sparkline(latencies: &VecDeque<Duration>) -> String {
+    let (fastest, slowest) = match (latencies.iter().min(), latencies.iter().max()) {
+        (Some(fastest), Some(slowest)) => (fastest.as_secs_f64(), slowest.as_secs_f64()),
+        _ => return String::new(),
+    };
+    let range = slowest - fastest;
+
+    return latencies.iter().map(|latency| {
+        if range == 0.0 {
+            return SPARKS[0];
+        }
+        let height = (latency.as_secs_f64() - fastest) / range * (SPARKS.len() - 1) as f64;
+        return SPARKS[height.round() as usize];
+    }).collect();
+}
@@ -0,0 +1,138 @@
+use std::io::{self, ErrorKind};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+
+/* Looks `host` up by asking `server` directly, rather than going through the
+   system resolver and whatever caching it does on the way.
+
+   This is just enough of a DNS client for that: an A and an AAAA query over
+   UDP, with the answer's addresses taken from however many records came
+   back. A truncated answer is used as far as it goes - we don't retry over
+   TCP. The IPv4 addresses come first. */
+pub fn lookup(server: SocketAddr, host: &str, timeout: Duration) -> io::Result<Vec<IpAddr>> {
+    if let Ok(address) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        return Ok(vec![address]);
+    }
+
+    let local: SocketAddr = if server.is_ipv4() { "0.0.0.0:0".parse().unwrap() } else { "[::]:0".parse().unwrap() };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+    socket.set_read_timeout(Some(timeout))?;
+
+    // not random, but nothing's trying to spoof us - it only has to tell
+    // our two queries apart from each other and from anything stale.
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.subsec_nanos()).unwrap_or(0) as u16;
+    let queries = [(seed, TYPE_A), (seed.wrapping_add(1), TYPE_AAAA)];
+    for (id, record_type) in queries.iter() {
+        socket.send(&query(*id, host, *record_type)?)?;
+    }
+
+    let mut answers: [Option<Vec<IpAddr>>; 2] = [None, None];
+    let mut packet = [0; 4096];
+    while answers.iter().any(|answer| answer.is_none()) {
+        let length = match socket.recv(&mut packet) {
+            Ok(length) => length,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                return Err(io::Error::other(format!("failed to lookup address information: no answer from {}", server)));
+            }
+            Err(e) => return Err(e),
+        };
+        let packet = &packet[..length];
+        if packet.len() < 12 {
+            continue;
+        }
+        let id = u16::from_be_bytes([packet[0], packet[1]]);
+        if let Some(index) = queries.iter().position(|(query_id, _)| *query_id == id) {
+            answers[index] = Some(parse_answer(packet, host)?);
+        }
+    }
+
+    let addresses: Vec<IpAddr> = answers.iter_mut().flat_map(|answer| answer.take().unwrap()).collect();
+    if addresses.is_empty() {
+        return Err(io::Error::other(format!("failed to lookup address information: {} has no A or AAAA records", host)));
+    }
+
+    return Ok(addresses);
+}
+
+/* A recursive query for `record_type` records of `host` */
+fn query(id: u16, host: &str, record_type: u16) -> io::Result<Vec<u8>> {
+    let mut packet = Vec::with_capacity(18 + host.len());
+    packet.extend_from_slice(&id.to_be_bytes());
+    // just "recursion desired" set, and one question.
+    packet.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+    for label in host.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("{:?} is not a name DNS can look up", host)));
+        }
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+    packet.extend_from_slice(&record_type.to_be_bytes());
+    // class IN.
+    packet.extend_from_slice(&[0x00, 0x01]);
+
+    return Ok(packet);
+}
+
+/* The addresses in the answer section of a response. CNAMEs and the like are
+   skipped over - a recursive server sends the records they lead to as well. */
+fn parse_answer(packet: &[u8], host: &str) -> io::Result<Vec<IpAddr>> {
+    let malformed = || io::Error::new(ErrorKind::InvalidData, "malformed DNS response");
+
+    match packet[3] & 0x0f {
+        0 => {}
+        3 => return Err(io::Error::other(format!("failed to lookup address information: {} does not exist (NXDOMAIN)", host))),
+        code => return Err(io::Error::other(format!("failed to lookup address information: the server answered with error code {}", code))),
+    }
+
+    let questions = u16::from_be_bytes([packet[4], packet[5]]);
+    let answers = u16::from_be_bytes([packet[6], packet[7]]);
+    let mut position = 12;
+    for _ in 0..questions {
+        // the name, then its type and class.
+        position = skip_name(packet, position).ok_or_else(malformed)? + 4;
+    }
+
+    let mut addresses = Vec::new();
+    for _ in 0..answers {
+        position = skip_name(packet, position).ok_or_else(malformed)?;
+        let fixed = packet.get(position..position + 10).ok_or_else(malformed)?;
+        let record_type = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        position += 10;
+        let data = packet.get(position..position + length).ok_or_else(malformed)?;
+        position += length;
+
+        match (record_type, length) {
+            (TYPE_A, 4) => addresses.push(IpAddr::V4(Ipv4Addr::new(data[0], data[1], data[2], data[3]))),
+            (TYPE_AAAA, 16) => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(data);
+                addresses.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+    }
+
+    return Ok(addresses);
+}
+
+/* Where the name starting at `position` ends, or None if it runs off the end */
+fn skip_name(packet: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let length = *packet.get(position)? as usize;
+        if length == 0 {
+            return Some(position + 1);
+        }
+        // a pointer to a name elsewhere in the packet ends this one.
+        if length & 0xc0 == 0xc0 {
+            return Some(position + 2);
+        }
+        position += 1 + length;
+    }
+}
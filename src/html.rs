@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use crate::connect::{format_duration, TimeUnit};
+use crate::statistics::{percentile, Statistics};
+
+const PERCENTILES: [f64; 6] = [50.0, 75.0, 90.0, 95.0, 99.0, 99.9];
+
+// the histogram's bars, and how much room it takes on the page.
+const HISTOGRAM_BINS: usize = 20;
+const CHART_WIDTH: f64 = 600.0;
+const CHART_HEIGHT: f64 = 200.0;
+const AXIS_HEIGHT: f64 = 20.0;
+
+const STYLE: &str = "body { font-family: sans-serif; margin: 2em auto; max-width: 50em; color: #222 }
+table { border-collapse: collapse; margin: 1em 0 }
+th, td { padding: 0.3em 1em; border-bottom: 1px solid #ddd; text-align: left }
+td.number { text-align: right; font-variant-numeric: tabular-nums }
+rect { fill: #4a7ebb }
+text { font-size: 11px; fill: #555 }";
+
+/* For `--html`: a page of its own for sharing a run's results, with one
+   section per URL profiled.
+
+   Everything's inline - styles and SVG charts alike - so the single file can
+   be mailed around or attached to a ticket and still look the same. */
+pub fn render(all_statistics: &[Statistics], unit: Option<TimeUnit>) -> String {
+    let mut page = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>HTTP profile</title>\n<style>\n{}\n</style>\n</head>\n<body>\n<h1>HTTP profile</h1>\n",
+        STYLE
+    );
+    for statistics in all_statistics.iter() {
+        page.push_str(&section(statistics, unit));
+    }
+    page.push_str("</body>\n</html>\n");
+
+    return page;
+}
+
+fn section(statistics: &Statistics, unit: Option<TimeUnit>) -> String {
+    let duration = |duration: Option<Duration>| duration.map_or(String::from("n/a"), |duration| format_duration(unit, duration));
+    let size = |size: Option<usize>| size.map_or(String::from("n/a"), |size| format!("{} B", size));
+
+    let mut html = format!("<h2>{}</h2>\n", escape(&statistics.url));
+    html.push_str("<table>\n");
+    let rows = [
+        ("Requests", statistics.total_requests.to_string()),
+        ("Succeeded", format!("{} ({:.1}%)", statistics.successful_requests, statistics.percentage_succeeded)),
+        ("Failed", statistics.failed_requests.to_string()),
+        (
+            "Responses by status",
+            statistics.status_buckets.iter()
+                .filter(|(bucket, count)| **bucket != "other" || **count > 0)
+                .map(|(bucket, count)| format!("{} {}", bucket, count))
+                .collect::<Vec<String>>().join(", "),
+        ),
+        ("Fastest", duration(statistics.fastest)),
+        ("Mean", duration(statistics.mean)),
+        ("Median", duration(statistics.median)),
+        ("Slowest", duration(statistics.slowest)),
+        ("Smallest", size(statistics.smallest_size)),
+        ("Largest", size(statistics.largest_size)),
+    ];
+    for (name, value) in rows.iter() {
+        html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, escape(value)));
+    }
+    html.push_str("</table>\n");
+
+    if statistics.sorted_durations.is_empty() {
+        html.push_str("<p>No successful responses, so there are no latencies to chart.</p>\n");
+        return html;
+    }
+
+    html.push_str("<h3>Percentiles</h3>\n<table>\n<tr><th>Percentile</th><th>Response time</th></tr>\n");
+    for p in PERCENTILES.iter() {
+        html.push_str(&format!(
+            "<tr><td>p{}</td><td class=\"number\">{}</td></tr>\n", p, duration(percentile(&statistics.sorted_durations, *p))
+        ));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h3>Response times</h3>\n");
+    html.push_str(&histogram(&statistics.sorted_durations, unit));
+
+    return html;
+}
+
+/* Latencies in HISTOGRAM_BINS equal slices between the fastest and slowest, as an SVG bar chart */
+fn histogram(sorted_durations: &[Duration], unit: Option<TimeUnit>) -> String {
+    // safe to unwrap, the caller has checked there's at least one.
+    let (fastest, slowest) = (*sorted_durations.first().unwrap(), *sorted_durations.last().unwrap());
+    let range = (slowest - fastest).as_secs_f64();
+    let mut counts = [0usize; HISTOGRAM_BINS];
+    for duration in sorted_durations.iter() {
+        let position = if range == 0.0 { 0.0 } else { (*duration - fastest).as_secs_f64() / range };
+        counts[((position * HISTOGRAM_BINS as f64) as usize).min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let tallest = *counts.iter().max().unwrap() as f64;
+    let bar_width = CHART_WIDTH / HISTOGRAM_BINS as f64;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" role=\"img\">\n",
+        CHART_WIDTH, CHART_HEIGHT + AXIS_HEIGHT
+    );
+    for (bin, count) in counts.iter().enumerate() {
+        let height = *count as f64 / tallest * CHART_HEIGHT;
+        let bin_start = fastest + Duration::from_secs_f64(range * bin as f64 / HISTOGRAM_BINS as f64);
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\"><title>{} responses from {}</title></rect>\n",
+            bin as f64 * bar_width + 1.0, CHART_HEIGHT - height, bar_width - 2.0, height,
+            count, escape(&format_duration(unit, bin_start))
+        ));
+    }
+    svg.push_str(&format!(
+        "<text x=\"0\" y=\"{}\">{}</text>\n<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+        CHART_HEIGHT + AXIS_HEIGHT - 5.0, escape(&format_duration(unit, fastest)),
+        CHART_WIDTH, CHART_HEIGHT + AXIS_HEIGHT - 5.0, escape(&format_duration(unit, slowest))
+    ));
+    svg.push_str("</svg>\n");
+
+    return svg;
+}
+
+fn escape(text: &str) -> String {
+    return text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+}
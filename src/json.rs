@@ -0,0 +1,349 @@
+/* Just enough of a JSON value for us to serialise our own output with */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /* Builds an object, keeping fields in the order given */
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        return Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect());
+    }
+
+    /* On one line, which is what log ingestion wants */
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, None);
+        return out;
+    }
+
+    /* Indented by two spaces a level, for people to read */
+    pub fn render_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, Some(0));
+        out.push('\n');
+        return out;
+    }
+
+    // `depth` is how deeply nested we are when pretty-printing, or None for compact output.
+    fn write(&self, out: &mut String, depth: Option<usize>) {
+        match self {
+            Json::Null => out.push_str("null"),
+            // JSON has no way to spell NaN or infinity.
+            Json::Number(value) if !value.is_finite() => out.push_str("null"),
+            Json::Number(value) if value.fract() == 0.0 && value.abs() < 1e15 => {
+                out.push_str(&format!("{}", *value as i64));
+            }
+            Json::Number(value) => out.push_str(&format!("{}", value)),
+            Json::String(value) => write_string(value, out),
+            Json::Array(values) => {
+                out.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    new_line(out, depth.map(|depth| depth + 1));
+                    value.write(out, depth.map(|depth| depth + 1));
+                }
+                if !values.is_empty() {
+                    new_line(out, depth);
+                }
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push('{');
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        out.push(',');
+                    }
+                    new_line(out, depth.map(|depth| depth + 1));
+                    write_string(key, out);
+                    out.push(':');
+                    if depth.is_some() {
+                        out.push(' ');
+                    }
+                    value.write(out, depth.map(|depth| depth + 1));
+                }
+                if !fields.is_empty() {
+                    new_line(out, depth);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+
+/* Starts a new, indented line when pretty-printing - and does nothing otherwise */
+fn new_line(out: &mut String, depth: Option<usize>) {
+    if let Some(depth) = depth {
+        out.push('\n');
+        out.push_str(&"  ".repeat(depth));
+    }
+}
+
+fn write_string(value: &str, out: &mut String) {
+    out.push('"');
+    for character in value.chars() {
+        match character {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+impl From<f64> for Json {
+    fn from(value: f64) -> Json {
+        return Json::Number(value);
+    }
+}
+
+impl From<usize> for Json {
+    fn from(value: usize) -> Json {
+        return Json::Number(value as f64);
+    }
+}
+
+impl From<i32> for Json {
+    fn from(value: i32) -> Json {
+        return Json::Number(value as f64);
+    }
+}
+
+impl From<&str> for Json {
+    fn from(value: &str) -> Json {
+        return Json::String(value.to_string());
+    }
+}
+
+impl From<String> for Json {
+    fn from(value: String) -> Json {
+        return Json::String(value);
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Json {
+        return match value {
+            Some(inner) => inner.into(),
+            None => Json::Null,
+        };
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(values: Vec<T>) -> Json {
+        return Json::Array(values.into_iter().map(|value| value.into()).collect());
+    }
+}
+
+// nesting any deeper than this is more likely an attack than an API.
+const MAX_DEPTH: usize = 512;
+
+/* Checks `text` is a single well-formed JSON value, per RFC 8259, without
+   building anything from it - or says where it stops being one */
+pub fn validate(text: &str) -> Result<(), String> {
+    let mut validator = Validator{ bytes: text.as_bytes(), position: 0 };
+    validator.skip_whitespace();
+    validator.value(0)?;
+    validator.skip_whitespace();
+    if validator.position < validator.bytes.len() {
+        return Err(validator.unexpected());
+    }
+
+    return Ok(());
+}
+
+struct Validator<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Validator<'a> {
+    fn value(&mut self, depth: usize) -> Result<(), String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("nested more than {} deep at byte {}", MAX_DEPTH, self.position));
+        }
+
+        return match self.peek() {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => self.string(),
+            Some(b't') => self.literal("true"),
+            Some(b'f') => self.literal("false"),
+            Some(b'n') => self.literal("null"),
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(self.unexpected()),
+        };
+    }
+
+    fn object(&mut self, depth: usize) -> Result<(), String> {
+        self.position += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.position += 1;
+            return Ok(());
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.unexpected());
+            }
+            self.string()?;
+            self.skip_whitespace();
+            self.expect(b':')?;
+            self.skip_whitespace();
+            self.value(depth + 1)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b'}') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<(), String> {
+        self.position += 1;
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.position += 1;
+            return Ok(());
+        }
+
+        loop {
+            self.skip_whitespace();
+            self.value(depth + 1)?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.position += 1,
+                Some(b']') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<(), String> {
+        self.position += 1;
+        loop {
+            match self.peek() {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(());
+                }
+                Some(b'\\') => {
+                    self.position += 1;
+                    match self.peek() {
+                        Some(b'"') | Some(b'\\') | Some(b'/') | Some(b'b') | Some(b'f') | Some(b'n') | Some(b'r') | Some(b't') => self.position += 1,
+                        Some(b'u') => {
+                            self.position += 1;
+                            for _ in 0..4 {
+                                if !self.peek().is_some_and(|byte| byte.is_ascii_hexdigit()) {
+                                    return Err(self.unexpected());
+                                }
+                                self.position += 1;
+                            }
+                        }
+                        _ => return Err(self.unexpected()),
+                    }
+                }
+                // control characters have to be escaped.
+                Some(byte) if byte >= 0x20 => self.position += 1,
+                _ => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<(), String> {
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        match self.peek() {
+            // no leading zeroes, so a zero stands alone.
+            Some(b'0') => self.position += 1,
+            Some(b'1'..=b'9') => self.digits(),
+            _ => return Err(self.unexpected()),
+        }
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            self.required_digits()?;
+        }
+        if let Some(b'e') | Some(b'E') = self.peek() {
+            self.position += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.position += 1;
+            }
+            self.required_digits()?;
+        }
+
+        return Ok(());
+    }
+
+    fn required_digits(&mut self) -> Result<(), String> {
+        if !self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            return Err(self.unexpected());
+        }
+        self.digits();
+
+        return Ok(());
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            self.position += 1;
+        }
+    }
+
+    fn literal(&mut self, word: &str) -> Result<(), String> {
+        if !self.bytes[self.position..].starts_with(word.as_bytes()) {
+            return Err(self.unexpected());
+        }
+        self.position += word.len();
+
+        return Ok(());
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() != Some(byte) {
+            return Err(self.unexpected());
+        }
+        self.position += 1;
+
+        return Ok(());
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        return self.bytes.get(self.position).cloned();
+    }
+
+    fn unexpected(&self) -> String {
+        return match self.peek() {
+            Some(byte) if byte.is_ascii_graphic() => format!("unexpected {:?} at byte {}", byte as char, self.position),
+            Some(byte) => format!("unexpected byte 0x{:02x} at byte {}", byte, self.position),
+            None if self.bytes.is_empty() => String::from("the body is empty"),
+            None => format!("ends early, at byte {}", self.position),
+        };
+    }
+}
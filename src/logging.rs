@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/* Verbosity levels, from least to most chatty */
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+}
+
+impl Level {
+    pub fn from_flag(flag: &str) -> Option<Level> {
+        return match flag {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        };
+    }
+
+    /* Maps `-v` repetitions onto a level, starting from the default */
+    pub fn from_verbosity(occurrences: u64) -> Level {
+        return match occurrences {
+            0 => Level::Warn,
+            1 => Level::Info,
+            _ => Level::Debug,
+        };
+    }
+
+    pub fn label(&self) -> &'static str {
+        return match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        };
+    }
+}
+
+// a global is the least intrusive option here - otherwise every function
+// that might want to log would need a logger threaded through to it.
+static LEVEL: AtomicUsize = AtomicUsize::new(Level::Warn as usize);
+
+pub fn set_level(level: Level) {
+    LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    return level as usize <= LEVEL.load(Ordering::Relaxed);
+}
+
+// everything goes to stderr, so diagnostics never end up mixed in with the
+// results we print on stdout.
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::logging::enabled($level) {
+            eprintln!("[{}] {}", $level.label(), format!($($arg)*));
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Error, $($arg)*) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Warn, $($arg)*) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Info, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { log_at!($crate::logging::Level::Debug, $($arg)*) };
+}
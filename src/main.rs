@@ -1,11 +1,47 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::net::{IpAddr, SocketAddr};
 use std::process;
+use std::thread;
+use std::time::Duration;
 use url::Url;
 
 #[macro_use]
 extern crate clap;
 
+#[macro_use]
+mod logging;
+mod assertions;
+mod color;
+mod config;
 mod connect;
-use crate::connect::Profiler;
+mod cookies;
+mod dashboard;
+mod dns;
+mod html;
+mod json;
+mod quantiles;
+mod random;
+mod serve;
+mod sigv4;
+mod socket;
+mod statistics;
+mod statsd;
+mod template;
+mod throttle;
+use crate::assertions::Assertion;
+use crate::quantiles::STREAMING_QUANTILES;
+use crate::color::{paint, Color};
+use crate::config::ProfilerConfig;
+use crate::cookies::CookieJar;
+use crate::connect::{format_duration, tls_connector, Profiler, TimeUnit, BUILT_IN_USER_AGENTS, DEFAULT_CONNECT_TIMEOUT, DEFAULT_LATENCY_BUCKETS, DEFAULT_MAX_RESPONSE_SIZE, DEFAULT_RETRIES};
+use crate::statistics::{mean, percentile, standard_deviation, Statistics};
+use crate::sigv4::SigV4;
+use crate::template::Template;
+use itertools::Itertools;
+use crate::json::Json;
 
 fn main() {
 
@@ -13,38 +49,1445 @@ fn main() {
         (version: "0.1")
         (author: "Akshat Mahajan <akshatm.bkk@gmail.com>")
         (about: "Profile website latency.")
-        (@arg URL: -u --url +takes_value +required "Value of URL to profile (defaults to 1 if omitted)")
-        (@arg PROFILE: -p --profile +takes_value "Number of requests to make")
+        (@arg URL: -u --url +takes_value "Value of URL to profile, or `-` to read URLs line-by-line from stdin")
+        (@arg PROFILE: -p --profile +takes_value "Number of requests to make (defaults to 1 if omitted)")
+        (@arg RUNS: --runs +takes_value conflicts_with[HANDSHAKE_ONLY] "Repeat the whole profile this many times, reporting each run and then all of them together")
+        (@arg REST: --rest +takes_value requires[RUNS] "Seconds to sit idle between runs, so pools and caches can cool (defaults to 0)")
+        (@arg FOR: --for +takes_value conflicts_with[PROFILE RAMP] "Keep making requests for this long instead of a fixed number, e.g. 30s, 5m or 500ms")
+        (@arg CONCURRENCY: --concurrency +takes_value "Keep this many requests in flight at once, each on its own connection (defaults to 1)")
+        (@arg RAMP: --ramp requires[CONCURRENCY] conflicts_with[RUNS] "Step concurrency up from 1 to --concurrency, reporting latency and throughput at each step")
+        (@arg RAMP_STEPS: --("ramp-steps") +takes_value requires[RAMP] "How many concurrency levels --ramp tries, including 1 and --concurrency (defaults to 5)")
+        (@arg ADDRESS_INDEX: --("address-index") +takes_value conflicts_with[ALL_ADDRESSES] "Only ever connect to the Nth address the host resolves to, counting from 0")
+        (@arg COMPARE_SCHEMES: --("compare-schemes") +takes_value #{0, 1} conflicts_with[ALL_ADDRESSES RAMP RUNS SWEEP HANDSHAKE_ONLY TEMPLATE] "Profile the URL over both http and https, and report what TLS costs; the value, if any, is the other scheme's port")
+        (@arg SHOW_RESOLUTION: --("show-resolution") conflicts_with[JSONL TEMPLATE] "Print the addresses the host resolved to before profiling, and which of them the first connection went to")
+        (@arg ALL_ADDRESSES: --("all-addresses") conflicts_with[RAMP RUNS] "Profile each address the host resolves to separately, and compare their latencies")
+        (@arg THROTTLE: --throttle +takes_value "Slow every connection to this many bytes per second each way, as if on a slow link")
+        (@arg MAX_TOTAL_BYTES: --("max-total-bytes") +takes_value conflicts_with[HANDSHAKE_ONLY] "Stop the run once the responses so far add up to this many bytes, and report on those")
+        (@arg ABORT_AFTER_FAILURES: --("abort-after-failures") +takes_value "Stop the run once this many requests have failed, and report on those made so far")
+        (@arg READ_CHUNK_SIZE: --("read-chunk-size") +takes_value conflicts_with[HANDSHAKE_ONLY] "Read responses this many bytes at a time, and report how fast bodies came in and the longest stall")
+        (@arg MAX_RESPONSE_SIZE: --("max-response-size") +takes_value "Abort any response larger than this many bytes (defaults to 64 MiB)")
+        (@arg METHOD: --method +takes_value conflicts_with[HEAD] "Send this method instead of GET (or POST with --data-file), e.g. PURGE or PATCH")
+        (@arg DATA_FILE: --("data-file") +takes_value "POST the contents of this file, byte for byte, as the request body")
+        (@arg EXPECT_CONTINUE: --("expect-continue") requires[DATA_FILE] "Send Expect: 100-continue, and only send the body once the server asks for it")
+        (@arg HEAD: --head conflicts_with[DATA_FILE] "Send HEAD requests, which only fetch the status line and headers")
+        (@arg FOLLOW_REDIRECTS: --("follow-redirects") "Follow redirects to the final response, timing each hop along the way")
+        (@arg ACCEPT: --accept +takes_value +multiple "Accept header to send (defaults to */*); given more than once, requests take turns and each is reported on")
+        (@arg ROTATE_USER_AGENT: --("rotate-user-agent") "Cycle through a built-in list of user agents across requests, and report latency for each")
+        (@arg SWEEP: --sweep +takes_value conflicts_with[HANDSHAKE_ONLY] "Health-check every path in this file, one per line, with HEAD requests pipelined over one connection to --url's host")
+        (@arg PATH_WEIGHTS: --("path-weights") +takes_value conflicts_with[SWEEP HANDSHAKE_ONLY] "Spread requests across the paths in this file of `path weight` lines, each picked in proportion to its weight, and report each path's latency")
+        (@arg USER_AGENT_FILE: --("user-agent-file") +takes_value "Cycle through the user agents in this file, one per line, instead of the built-in list")
+        (@arg INCLUDE_HEADERS_IN_BODY: --("include-headers-in-body") "Keep the status line and headers in the response we show, for debugging the exchange itself")
+        (@arg COOKIE: --cookie +takes_value "Send these cookies with every request, e.g. \"session=abc; theme=dark\"")
+        (@arg COOKIE_JAR: --("cookie-jar") +takes_value "Load cookies from this Netscape-format file, and save it back with any the server set")
+        (@arg TRACE_HEADER: --("trace-header") +takes_value "Send a fresh trace ID in this header, e.g. traceparent or X-Request-Id, and list the slowest requests' IDs")
+        (@arg AWS_SIGV4: --("aws-sigv4") +takes_value "Sign every request with AWS SigV4 for region/service, e.g. us-east-1/execute-api, using AWS_ACCESS_KEY_ID and friends")
+        (@arg REFERENCE_BODY: --("reference-body") +takes_value "Abandon the run unless the first successful response's body matches this file exactly")
+        (@arg EXPECT_HEADER: --("expect-header") +takes_value +multiple "Check every response carries this header, as \"Name\" or \"Name: Value\" (repeatable)")
+        (@arg KEEP_ALIVE: --("keep-alive") "Reuse connections between requests until the server closes them or hits its Keep-Alive max")
+        (@arg JITTER: --jitter +takes_value conflicts_with[HANDSHAKE_ONLY] "Wait a random 0 to this many milliseconds before each request, so they don't arrive perfectly spaced")
+        (@arg SEED: --seed +takes_value "Seed everything random about a run, e.g. --jitter delays, with this number - to replay a run the summary gave the seed of")
+        (@arg IDLE_PROBE: --("idle-probe") +takes_value requires[KEEP_ALIVE] "Leave each kept-alive connection idle this long before reusing it, e.g. 5s, to find the server's idle timeout")
+        (@arg WARM_POOL: --("warm-pool") +takes_value requires[KEEP_ALIVE] conflicts_with[HANDSHAKE_ONLY] "Open this many connections before timing begins, and send the measured requests over them")
+        (@arg REPEAT_WITH_CACHE_HEADERS: --("repeat-with-cache-headers") conflicts_with[HANDSHAKE_ONLY] "Send the first response's ETag and Last-Modified back with every request after it, and compare 304 latency with full 200s")
+        (@arg COMPARE_CACHE: --("compare-cache") "Report the first (cold) response's latency against the median of the rest (warm)")
+        (@arg CACERT: --cacert +takes_value "Also trust the CA certificates in this PEM file, e.g. for an internal CA")
+        (@arg ABORT_ON_TLS_ERROR: --("abort-on-tls-error") conflicts_with[SKIP_TLS_ERROR] "Stop profiling as soon as any TLS handshake fails")
+        (@arg SKIP_TLS_ERROR: --("skip-tls-error") "Record failed TLS handshakes as failed requests and carry on (default)")
+        (@arg PREFLIGHT: --preflight "Time a CORS preflight (OPTIONS) request ahead of every measured request")
+        (@arg ORIGIN: --origin +takes_value requires[PREFLIGHT] "Origin header to send with preflights (defaults to http://localhost)")
+        (@arg PROXY: --proxy +takes_value conflicts_with[CONNECT_TO] "Send requests through this HTTP proxy, e.g. http://proxy:3128 - HTTPS ones through a CONNECT tunnel")
+        (@arg RESOLVE: --resolve +takes_value +multiple "Use these addresses for a host and port instead of DNS, as host:port:addr[,addr...] (repeatable)")
+        (@arg PROXY_USER: --("proxy-user") +takes_value requires[PROXY] "Authenticate to the --proxy as user:pass, with Basic authentication")
+        (@arg CONNECT_TO: --("connect-to") +takes_value "Connect to this host:port instead, while still requesting the URL's host")
+        (@arg DNS_SERVER: --("dns-server") +takes_value "Look the target up with this nameserver, as ip or ip:port, instead of the system resolver")
+        (@arg INTERFACE: --interface +takes_value "Send every request from this local IP address, e.g. to pick a NIC or VPN on a multi-homed host")
+        (@arg MAX_MEAN_SIZE: --("max-mean-size") +takes_value "Fail the run if the mean response body is larger than this many bytes")
+        (@arg MAX_SIZE: --("max-size") +takes_value "Fail the run if any response body is larger than this many bytes")
+        (@arg ASSERT: --assert +takes_value +multiple "Fail the run unless this holds, e.g. \"p99<100ms\" or \"success_rate>=99.5\" (repeatable)")
+        (@arg MIN_SAMPLES: --("min-samples") +takes_value "Warn that the statistics are unreliable when fewer than this many responses succeeded")
+        (@arg REQUIRE_MIN_SAMPLES: --("require-min-samples") requires[MIN_SAMPLES] "Exit with a non-zero status when fewer than --min-samples responses succeeded")
+        (@arg FAIL_ON_SERVER_ERROR: --("fail-on-server-error") "Exit with a non-zero status if any response was a 5xx")
+        (@arg STATSD: --statsd +takes_value "Send mean and p99 latency and the success rate to this StatsD collector (host:port) after each run")
+        (@arg TEMPLATE: --template +takes_value conflicts_with[JSONL RAMP ALL_ADDRESSES] "Print only this, filled in from each URL's results, in place of the summary: e.g. \"{url} {p99}ms\\n\"")
+        (@arg DASHBOARD: --dashboard conflicts_with[JSONL TEMPLATE] "Show a live panel of the request count, success rate, mean and recent latencies while profiling")
+        (@arg JSONL: --jsonl "Write a JSON object per request to stdout as each completes, instead of the summary")
+        (@arg PRETTY: --pretty requires[JSON_FILE] "Indent the --json-file output for reading, rather than keeping it on one line")
+        (@arg DUMP_SAMPLES: --("dump-samples") +takes_value "Write every successful request's latency to this file, one per line in microseconds")
+        (@arg JSON_FILE: --("json-file") +takes_value "Once the run completes, atomically write its statistics as JSON to this path (an array of them when reading URLs from stdin)")
+        (@arg HTML: --html +takes_value conflicts_with[HANDSHAKE_ONLY] "Once the run completes, write its statistics to this path as a self-contained HTML report, with a latency histogram")
+        (@arg LOG_LEVEL: --("log-level") +takes_value possible_value[error warn info debug] "How much diagnostic output to write to stderr (defaults to warn)")
+        (@arg VERBOSE: -v ... "Increase diagnostic output on stderr (-v for info, -vv for debug)")
+        (@arg CONNECT_TIMEOUT: --("connect-timeout") +takes_value "Give up on each address the host resolves to after this many seconds without a connection (defaults to 5)")
+        (@arg TIMEOUT: --timeout +takes_value "Fail any request that takes longer than this many seconds in total")
+        (@arg HANDSHAKE_ONLY: --("handshake-only") "Only time TLS handshakes against an https URL, without sending any HTTP requests")
+        (@arg SLOW_THRESHOLD: --("slow-threshold") +takes_value "Count and report responses that took longer than this many milliseconds as slow")
+        (@arg TOTAL_TIME: --("total-time") "Also report percentiles of each request's total time, including DNS, connecting and TLS")
+        (@arg CONFIDENCE: --confidence "Report a 95% confidence interval for the mean response time")
+        (@arg TEE: --tee "Copy every raw response to stderr as it arrives, for watching what the server sends")
+        (@arg TEE_BYTES: --("tee-bytes") +takes_value requires[TEE] "Only copy the first this many bytes of each response with --tee")
+        (@arg STRICT_PARSE: --("strict-parse") "Count responses that aren't HTTP at all as failures, instead of successes with status 0")
+        (@arg HASH_BODIES: --("hash-bodies") "Report how many distinct response bodies came back, and how often each did")
+        (@arg VALIDATE_JSON_BODY: --("validate-json-body") conflicts_with[HEAD HANDSHAKE_ONLY] "Count responses whose body isn't valid JSON as failures, e.g. an HTML error page sent with a 200")
+        (@arg BUCKETS: --buckets +takes_value #{0, 1} "Report what share of requests finished under each of these latencies, e.g. 20ms,200ms,2s (defaults to 10ms,50ms,100ms,250ms,500ms,1s)")
+        (@arg APDEX_THRESHOLD: --("apdex-threshold") +takes_value conflicts_with[HANDSHAKE_ONLY] "Score the run's Apdex against this target time T, in milliseconds")
+        (@arg RETRY_EMPTY: --("retry-empty") conflicts_with[HANDSHAKE_ONLY] "When the server sends nothing at all back, send the request again on a new connection instead of counting a status 0")
+        (@arg RETRIES: --retries +takes_value requires[RETRY_EMPTY] "How many times --retry-empty tries again before counting the request as failed (defaults to 3)")
+        (@arg STREAMING_STATS: --("streaming-stats") conflicts_with[HANDSHAKE_ONLY HTML BUCKETS APDEX_THRESHOLD DECILES CONFIDENCE COMPARE_SCHEMES RUNS RAMP SWEEP ALL_ADDRESSES] "Estimate p50, p95 and p99 as responses come in, in constant memory, instead of keeping every response - for very long --for runs")
+        (@arg DECILES: --deciles "Report the success rate for each tenth of the run, to show whether failures cluster")
+        (@arg COLOR: --color +takes_value possible_value[auto always never] "Colour the summary: auto (the default) colours it only when stdout is a terminal")
+        (@arg NO_COLOR: --("no-color") conflicts_with[COLOR] "Never colour the summary, same as --color never")
+        (@arg SERVE: --serve +takes_value +hidden "Instead of profiling, serve a fixed response on this localhost port, for testing against")
+        (@arg SERVE_STATUS: --("serve-status") +takes_value +hidden requires[SERVE] "Status code --serve answers with (defaults to 200)")
+        (@arg SERVE_BODY: --("serve-body") +takes_value +hidden requires[SERVE] "Body --serve answers with (defaults to \"ok\")")
+        (@arg SERVE_DELAY: --("serve-delay") +takes_value +hidden requires[SERVE] "Milliseconds --serve waits before answering each request (defaults to 0)")
+        (@arg UNIT: --unit +takes_value possible_value[s ms us] "Report all durations in this unit with fixed decimal places")
     )
     .get_matches();
 
+    // an explicit --log-level wins over however many -v's we were given,
+    // and either wins over HTTP_PROFILER_LOG_LEVEL.
+    let log_level = match matches.value_of("LOG_LEVEL").and_then(logging::Level::from_flag) {
+        Some(level) => level,
+        None if matches.occurrences_of("VERBOSE") > 0 => logging::Level::from_verbosity(matches.occurrences_of("VERBOSE")),
+        None => match env_default("LOG_LEVEL") {
+            Some(raw) => match logging::Level::from_flag(&raw) {
+                Some(level) => level,
+                None => {
+                    error!("HTTP_PROFILER_LOG_LEVEL must be one of error, warn, info or debug");
+                    process::exit(1);
+                }
+            },
+            None => logging::Level::from_verbosity(0),
+        },
+    };
+    logging::set_level(log_level);
+
+    // an explicit --color always wins; otherwise we follow the NO_COLOR
+    // convention (https://no-color.org) and only colour a terminal.
+    let colored = match matches.value_of("COLOR") {
+        Some("always") => true,
+        Some("never") => false,
+        _ => {
+            !matches.is_present("NO_COLOR")
+                && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && atty::is(atty::Stream::Stdout)
+        }
+    };
+    color::set_enabled(colored);
+
+    if let Some(port) = matches.value_of("SERVE") {
+        run_server(port, &matches);
+        return;
+    }
+
     // default to 1 if `profile` is not provided or not parsable as integer.
-    let number_of_requests: i64 = match matches.value_of("PROFILE") {
+    let number_of_requests: i64 = match flag_or_env(&matches, "PROFILE").as_deref() {
         Some(x) => x.parse::<i64>().map_or(1, |v| v),
         None => 1,
     };
     if number_of_requests <= 0 {
-        println!("The value to --profile must be greater than 0");
+        error!("The value to --profile must be greater than 0");
         process::exit(1);
     }
 
-    // safe to unwrap as Clap will complain about required values
-    // long before it allows the caller to get here.
-    let target = Url::parse(matches.value_of("URL").unwrap());
-    let target = match target {
-        Ok(value) => value,
+    let run_for = match matches.value_of("FOR") {
+        Some(x) => match parse_duration(x) {
+            Some(duration) if duration > Duration::new(0, 0) => Some(duration),
+            _ => {
+                error!("The value to --for must be a duration such as 30s, 5m or 500ms");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let concurrency: usize = match flag_or_env(&matches, "CONCURRENCY").as_deref() {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                error!("The value to --concurrency must be a positive number of connections");
+                process::exit(1);
+            }
+        },
+        None => 1,
+    };
+
+    let ramp_steps: Option<usize> = if matches.is_present("RAMP") {
+        match matches.value_of("RAMP_STEPS").unwrap_or("5").parse::<usize>() {
+            Ok(v) if v >= 2 => Some(v),
+            _ => {
+                error!("The value to --ramp-steps must be at least 2");
+                process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let address_index = match matches.value_of("ADDRESS_INDEX") {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) => Some(v),
+            Err(_) => {
+                error!("The value to --address-index must be a whole number, counting from 0");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let all_addresses = matches.is_present("ALL_ADDRESSES");
+    let show_resolution = matches.is_present("SHOW_RESOLUTION");
+    // the port for the scheme the URL doesn't have, if it isn't the usual one.
+    let compare_schemes: Option<Option<u16>> = match matches.value_of("COMPARE_SCHEMES") {
+        Some(x) => match x.parse::<u16>() {
+            Ok(v) if v > 0 => Some(Some(v)),
+            _ => {
+                error!("The value to --compare-schemes must be a port number");
+                process::exit(1);
+            }
+        },
+        None if matches.is_present("COMPARE_SCHEMES") => Some(None),
+        None => None,
+    };
+
+    let runs: i64 = match matches.value_of("RUNS") {
+        Some(x) => match x.parse::<i64>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                error!("The value to --runs must be a positive number of runs");
+                process::exit(1);
+            }
+        },
+        None => 1,
+    };
+
+    let rest = match matches.value_of("REST") {
+        Some(x) => match x.parse::<f64>() {
+            Ok(v) if v >= 0.0 && v.is_finite() => Duration::from_secs_f64(v),
+            _ => {
+                error!("The value to --rest must be a number of seconds, 0 or more");
+                process::exit(1);
+            }
+        },
+        None => Duration::from_secs(0),
+    };
+
+    let max_response_size: usize = match flag_or_env(&matches, "MAX_RESPONSE_SIZE").as_deref() {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                error!("The value to --max-response-size must be a positive number of bytes");
+                process::exit(1);
+            }
+        },
+        None => DEFAULT_MAX_RESPONSE_SIZE,
+    };
+
+    let max_total_bytes = match matches.value_of("MAX_TOTAL_BYTES") {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => Some(v),
+            _ => {
+                error!("The value to --max-total-bytes must be a positive number of bytes");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let abort_after_failures = match matches.value_of("ABORT_AFTER_FAILURES") {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => Some(v),
+            _ => {
+                error!("The value to --abort-after-failures must be a positive number of failures");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let read_chunk_size = match matches.value_of("READ_CHUNK_SIZE") {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => Some(v),
+            _ => {
+                error!("The value to --read-chunk-size must be a positive number of bytes");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let throttle: Option<u64> = match matches.value_of("THROTTLE") {
+        Some(x) => match x.parse::<u64>() {
+            Ok(v) if v > 0 => Some(v),
+            _ => {
+                error!("The value to --throttle must be a positive number of bytes per second");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // read once up front, rather than once per request.
+    let body = match matches.value_of("DATA_FILE") {
+        Some(path) => match fs::read(path) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                error!("Could not read --data-file {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let sweep_paths: Option<Vec<String>> = match matches.value_of("SWEEP") {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(contents) => Some(contents.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_string())
+                .collect()),
+            Err(e) => {
+                error!("Could not read --sweep {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    if sweep_paths.as_ref().is_some_and(|paths| paths.is_empty()) {
+        error!("--sweep has no paths in it");
+        process::exit(1);
+    }
+
+    let weighted_paths: Vec<(String, f64)> = match matches.value_of("PATH_WEIGHTS") {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(contents) => contents.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| match line.rsplit_once(char::is_whitespace) {
+                    Some((path, weight)) => match weight.parse::<f64>() {
+                        Ok(weight) if weight > 0.0 && weight.is_finite() => (path.trim_end().to_string(), weight),
+                        _ => {
+                            error!("The weight of {} in --path-weights must be a positive number, not {:?}", path.trim_end(), weight);
+                            process::exit(1);
+                        }
+                    },
+                    None => {
+                        error!("{:?} in --path-weights is not of the form `path weight`", line);
+                        process::exit(1);
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                error!("Could not read --path-weights {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => Vec::new(),
+    };
+    if matches.is_present("PATH_WEIGHTS") && weighted_paths.is_empty() {
+        error!("--path-weights has no paths in it");
+        process::exit(1);
+    }
+
+    let user_agents: Vec<String> = match matches.value_of("USER_AGENT_FILE") {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(contents) => contents.lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| line.to_string())
+                .collect(),
+            Err(e) => {
+                error!("Could not read --user-agent-file {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None if matches.is_present("ROTATE_USER_AGENT") => BUILT_IN_USER_AGENTS.iter().map(|agent| agent.to_string()).collect(),
+        None => Vec::new(),
+    };
+    if matches.is_present("USER_AGENT_FILE") && user_agents.is_empty() {
+        error!("--user-agent-file has no user agents in it");
+        process::exit(1);
+    }
+
+    let accepts: Vec<String> = matches.values_of("ACCEPT")
+        .map(|values| values.map(|value| value.trim().to_string()).collect())
+        .unwrap_or_default();
+    if accepts.iter().any(|accept| accept.is_empty()) {
+        error!("The value to --accept must be a media range, e.g. application/json");
+        process::exit(1);
+    }
+
+    let reference_body = match matches.value_of("REFERENCE_BODY") {
+        Some(path) => match fs::read(path) {
+            Ok(bytes) => Some(String::from_utf8_lossy(&bytes).to_string()),
+            Err(e) => {
+                error!("Could not read --reference-body {}: {}", path, e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let trace_header = matches.value_of("TRACE_HEADER").map(|name| name.to_string());
+    if trace_header.as_ref().is_some_and(|name| name.is_empty() || name.contains(|c: char| c == ':' || c.is_whitespace())) {
+        error!("The value to --trace-header must be a header name, e.g. X-Request-Id");
+        process::exit(1);
+    }
+
+    let aws_sigv4 = match matches.value_of("AWS_SIGV4") {
+        Some(scope) => match SigV4::from_env(scope) {
+            Ok(signer) => Some(signer),
+            Err(e) => {
+                error!("Cannot sign for --aws-sigv4: {}", e);
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let cookie_jar_path = matches.value_of("COOKIE_JAR");
+    let cookie_jar = match (matches.value_of("COOKIE"), cookie_jar_path) {
+        (None, None) => None,
+        (cookie, path) => {
+            let mut jar = match path {
+                // a jar that doesn't exist yet just starts out empty.
+                Some(path) if fs::metadata(path).is_ok() => match CookieJar::load(path) {
+                    Ok(jar) => jar,
+                    Err(e) => {
+                        error!("Could not read --cookie-jar {}: {}", path, e);
+                        process::exit(1);
+                    }
+                },
+                _ => CookieJar::default(),
+            };
+            if let Some(Err(e)) = cookie.map(|raw| jar.add_fixed(raw)) {
+                error!("Could not understand --cookie: {}", e);
+                process::exit(1);
+            }
+            Some(jar)
+        }
+    };
+
+    let expected_headers: Vec<(String, Option<String>)> = matches.values_of("EXPECT_HEADER")
+        .map(|values| values.map(|raw| {
+            let (name, value) = raw.split_once(':').unwrap_or((raw, ""));
+            let value = Some(value.trim()).filter(|value| !value.is_empty()).map(String::from);
+            return (name.trim().to_string(), value);
+        }).collect())
+        .unwrap_or_default();
+    if expected_headers.iter().any(|(name, _)| name.is_empty()) {
+        error!("The value to --expect-header must name a header, e.g. \"X-Frame-Options: DENY\"");
+        process::exit(1);
+    }
+
+    let keep_alive = matches.is_present("KEEP_ALIVE");
+    let idle_probe = match matches.value_of("IDLE_PROBE") {
+        Some(x) => match parse_duration(x) {
+            Some(duration) if duration > Duration::new(0, 0) => Some(duration),
+            _ => {
+                error!("The value to --idle-probe must be a duration such as 5s or 500ms");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let jitter = match matches.value_of("JITTER") {
+        Some(x) => match x.parse::<u64>() {
+            Ok(v) if v > 0 => Some(Duration::from_millis(v)),
+            _ => {
+                error!("The value to --jitter must be a positive number of milliseconds");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let seed = match matches.value_of("SEED").map(|x| x.parse::<u64>()) {
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(_)) => {
+            error!("The value to --seed must be a whole number, 0 or more");
+            process::exit(1);
+        }
+        None => None,
+    };
+    let warm_pool = match matches.value_of("WARM_POOL") {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => Some(v),
+            _ => {
+                error!("The value to --warm-pool must be a positive number of connections");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let min_samples = match matches.value_of("MIN_SAMPLES") {
+        Some(x) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => Some(v),
+            _ => {
+                error!("The value to --min-samples must be a positive number of responses");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let include_headers_in_body = matches.is_present("INCLUDE_HEADERS_IN_BODY");
+    let method = matches.value_of("METHOD").map(String::from);
+    // any token will do, as CDNs and caches make up their own - but it has
+    // to be one, or it'd break the request line.
+    if let Some(method) = &method {
+        let is_token_character = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        if method.is_empty() || !method.chars().all(is_token_character) {
+            error!("The value to --method must be an HTTP method, such as PURGE - {:?} has characters a method can't", method);
+            process::exit(1);
+        }
+        if method.chars().any(|c| c.is_ascii_lowercase()) {
+            warn!("Methods are case-sensitive, and servers may not recognize {:?} - did you mean {:?}?", method, method.to_ascii_uppercase());
+        }
+    }
+    // HEAD responses are framed differently, so it's not just another method.
+    let head = matches.is_present("HEAD") || method.as_deref() == Some("HEAD");
+    let method = method.filter(|method| method != "HEAD");
+    let follow_redirects = matches.is_present("FOLLOW_REDIRECTS");
+    let expect_continue = matches.is_present("EXPECT_CONTINUE");
+    let statsd = matches.value_of("STATSD");
+    // started afresh here, then added to as each URL is profiled.
+    let dump_samples = matches.value_of("DUMP_SAMPLES");
+    if let Some(path) = dump_samples {
+        if let Err(e) = fs::File::create(path) {
+            error!("Could not create --dump-samples {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    let handshake_only = matches.is_present("HANDSHAKE_ONLY");
+    let report_deciles = matches.is_present("DECILES");
+    let latency_buckets: Vec<Duration> = match matches.value_of("BUCKETS") {
+        Some(raw) => {
+            let mut thresholds = Vec::new();
+            for threshold in raw.split(',') {
+                match parse_duration(threshold) {
+                    Some(threshold) if threshold > Duration::from_secs(0) => thresholds.push(threshold),
+                    _ => {
+                        error!("The value to --buckets must be a comma-separated list of latencies, e.g. 20ms,200ms,2s, not {:?}", threshold);
+                        process::exit(1);
+                    }
+                }
+            }
+            thresholds.sort();
+            thresholds.dedup();
+            thresholds
+        }
+        None if matches.is_present("BUCKETS") => DEFAULT_LATENCY_BUCKETS.to_vec(),
+        None => Vec::new(),
+    };
+    let apdex_threshold = match matches.value_of("APDEX_THRESHOLD") {
+        Some(x) => match x.parse::<f64>() {
+            Ok(v) if v > 0.0 && v.is_finite() => Some(Duration::from_secs_f64(v / 1000_f64)),
+            _ => {
+                error!("The value to --apdex-threshold must be a positive number of milliseconds");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let report_confidence = matches.is_present("CONFIDENCE");
+    let hash_bodies = matches.is_present("HASH_BODIES");
+    let strict_parse = matches.is_present("STRICT_PARSE");
+    let validate_json_body = matches.is_present("VALIDATE_JSON_BODY");
+    let retry_empty = matches.is_present("RETRY_EMPTY");
+    let retries = match matches.value_of("RETRIES") {
+        Some(x) => match x.parse::<u32>() {
+            Ok(v) => v,
+            Err(_) => {
+                error!("The value to --retries must be a whole number, 0 or more");
+                process::exit(1);
+            }
+        },
+        None => DEFAULT_RETRIES,
+    };
+    let tee = match (matches.is_present("TEE"), matches.value_of("TEE_BYTES")) {
+        (false, _) => None,
+        (true, None) => Some(usize::MAX),
+        (true, Some(x)) => match x.parse::<usize>() {
+            Ok(v) if v > 0 => Some(v),
+            _ => {
+                error!("The value to --tee-bytes must be a positive number of bytes");
+                process::exit(1);
+            }
+        },
+    };
+    let report_total_time = matches.is_present("TOTAL_TIME");
+    let jsonl = matches.is_present("JSONL");
+    let dashboard = matches.is_present("DASHBOARD");
+    let streaming_stats = matches.is_present("STREAMING_STATS");
+    let template = match matches.value_of("TEMPLATE").map(Template::parse) {
+        Some(Ok(template)) => Some(template),
+        Some(Err(e)) => {
+            error!("Invalid --template: {}", e);
+            process::exit(1);
+        }
+        None => None,
+    };
+    // both `--jsonl` and `--template` take over stdout.
+    let summary = !jsonl && template.is_none();
+    let compare_cache = matches.is_present("COMPARE_CACHE");
+    let conditional_requests = matches.is_present("REPEAT_WITH_CACHE_HEADERS");
+    let abort_on_tls_error = matches.is_present("ABORT_ON_TLS_ERROR");
+    let cacert = matches.value_of("CACERT").map(String::from);
+    // found out now rather than on the first connection, as every one after would fail the same way.
+    if let Some(path) = &cacert {
+        // openssl's own errors are a mouthful for a file that isn't there.
+        if let Err(e) = fs::metadata(path) {
+            error!("Could not read --cacert {}: {}", path, e);
+            process::exit(1);
+        }
+        if let Err(e) = tls_connector(Some(path)) {
+            error!("Could not load CA certificates from --cacert {}: {}", path, e);
+            process::exit(1);
+        }
+    }
+    let preflight_origin = if matches.is_present("PREFLIGHT") {
+        Some(String::from(matches.value_of("ORIGIN").unwrap_or("http://localhost")))
+    } else {
+        None
+    };
+
+    let connect_to = matches.value_of("CONNECT_TO").map(String::from);
+    if let Some(address) = &connect_to {
+        let has_port = address.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok());
+        if !has_port {
+            error!("The value to --connect-to must be of the form host:port");
+            process::exit(1);
+        }
+    }
+
+    let mut resolve_overrides = BTreeMap::new();
+    for raw in matches.values_of("RESOLVE").into_iter().flatten() {
+        match parse_resolve(raw) {
+            Ok((host, port, addresses)) => {
+                resolve_overrides.insert((host, port), addresses);
+            }
+            Err(e) => {
+                error!("Invalid --resolve {:?}: {}", raw, e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let slow_threshold = match flag_or_env(&matches, "SLOW_THRESHOLD").as_deref() {
+        Some(x) => match x.parse::<f64>() {
+            Ok(v) if v > 0.0 && v.is_finite() => Some(Duration::from_secs_f64(v / 1000_f64)),
+            _ => {
+                error!("The value to --slow-threshold must be a positive number of milliseconds");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    // a bare address is the far more usual way to name a nameserver.
+    let dns_server = match matches.value_of("DNS_SERVER") {
+        Some(x) => match x.parse::<SocketAddr>().or_else(|_| x.parse::<IpAddr>().map(|address| SocketAddr::new(address, 53))) {
+            Ok(address) => Some(address),
+            Err(_) => {
+                error!("The value to --dns-server must be an IP address, optionally with a port, e.g. 1.1.1.1 or [2606:4700::1111]:53");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let interface = match matches.value_of("INTERFACE") {
+        Some(x) => match x.parse::<IpAddr>() {
+            Ok(address) => Some(address),
+            Err(_) => {
+                error!("The value to --interface must be a local IP address, e.g. 192.168.1.10");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let timeout = match flag_or_env(&matches, "TIMEOUT").as_deref() {
+        Some(x) => match x.parse::<f64>() {
+            Ok(v) if v > 0.0 && v.is_finite() => Some(Duration::from_secs_f64(v)),
+            _ => {
+                error!("The value to --timeout must be a positive number of seconds");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let connect_timeout = match flag_or_env(&matches, "CONNECT_TIMEOUT").as_deref() {
+        Some(x) => match x.parse::<f64>() {
+            Ok(v) if v > 0.0 && v.is_finite() => Duration::from_secs_f64(v),
+            _ => {
+                error!("The value to --connect-timeout must be a positive number of seconds");
+                process::exit(1);
+            }
+        },
+        None => DEFAULT_CONNECT_TIMEOUT,
+    };
+
+    let proxy = match matches.value_of("PROXY") {
+        Some(raw) => match Url::parse(raw) {
+            Ok(proxy) if proxy.scheme() == "http" && proxy.host_str().is_some() => Some(proxy),
+            _ => {
+                error!("The value to --proxy must be an http:// URL, e.g. http://proxy:3128");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let proxy_user = match flag_or_env(&matches, "PROXY_USER") {
+        Some(credentials) if credentials.contains(':') => Some(credentials),
+        Some(_) => {
+            error!("The value to --proxy-user must be of the form user:pass");
+            process::exit(1);
+        }
+        None => None,
+    };
+
+    // Clap has already restricted the flag to one of the units we
+    // understand, but not the environment.
+    let unit = match flag_or_env(&matches, "UNIT") {
+        Some(raw) => match TimeUnit::from_flag(&raw) {
+            Some(unit) => Some(unit),
+            None => {
+                error!("HTTP_PROFILER_UNIT must be one of s, ms or us");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let config = ProfilerConfig::new(number_of_requests)
+        .run_for(run_for)
+        .concurrency(concurrency)
+        .max_response_size(max_response_size)
+        .max_total_bytes(max_total_bytes)
+        .abort_after_failures(abort_after_failures)
+        .read_chunk_size(read_chunk_size)
+        .unit(unit)
+        .keep_alive(keep_alive)
+        .warm_pool(warm_pool)
+        .idle_probe(idle_probe)
+        .jitter(jitter)
+        .seed(seed)
+        .body(body)
+        .method(method)
+        .compare_cache(compare_cache)
+        .conditional_requests(conditional_requests)
+        .abort_on_tls_error(abort_on_tls_error)
+        .cacert(cacert)
+        .preflight_origin(preflight_origin)
+        .connect_to(connect_to)
+        .resolve(resolve_overrides)
+        .dns_server(dns_server)
+        .interface(interface)
+        .proxy(proxy.clone())
+        .proxy_user(proxy_user)
+        .timeout(timeout)
+        .connect_timeout(connect_timeout)
+        .throttle(throttle)
+        .handshake_only(handshake_only)
+        .report_deciles(report_deciles)
+        .latency_buckets(latency_buckets)
+        .apdex_threshold(apdex_threshold)
+        .report_confidence(report_confidence)
+        .min_samples(min_samples)
+        .hash_bodies(hash_bodies)
+        .strict_parse(strict_parse)
+        .validate_json_body(validate_json_body)
+        .retry_empty(retry_empty)
+        .retries(retries)
+        .tee(tee)
+        .report_total_time(report_total_time)
+        .slow_threshold(slow_threshold)
+        .include_headers_in_body(include_headers_in_body)
+        .jsonl(jsonl)
+        .dashboard(dashboard)
+        .streaming_stats(streaming_stats)
+        .show_resolution(show_resolution)
+        .expect_continue(expect_continue)
+        .head(head)
+        .follow_redirects(follow_redirects)
+        .user_agents(user_agents)
+        .weighted_paths(weighted_paths)
+        .accepts(accepts)
+        .expected_headers(expected_headers)
+        .trace_header(trace_header)
+        .aws_sigv4(aws_sigv4)
+        .reference_body(reference_body)
+        .cookie_jar(cookie_jar);
+
+    // every run gets a profiler of its own, so nothing carries over between
+    // them besides whatever the server and network remember.
+    let profile_target = |target: &Url, number_of_requests: i64| {
+        // resolved once up front, so a resolver rotating its answers can't
+        // move us onto a different backend partway through.
+        let config = config.clone().number_of_requests(number_of_requests);
+        let config = match address_index {
+            Some(index) => match resolve(target, &config).get(index) {
+                Some(address) => config.clone().pinned_address(Some(*address)),
+                None => {
+                    error!("--address-index {} is out of range for {}", index, target);
+                    process::exit(1);
+                }
+            },
+            None => config,
+        };
+        if show_resolution {
+            show_resolution_for(target, &config);
+        }
+        if all_addresses {
+            return profile_each_address(target, &config);
+        }
+        if let Some(other_port) = compare_schemes {
+            return compare_schemes_for(target, &config, other_port);
+        }
+        if let Some(steps) = ramp_steps {
+            return ramp(target, &config, steps);
+        }
+
+        let mut successful_responses = Vec::new();
+        let mut failed_responses = Vec::new();
+        let mut run_means = Vec::new();
+
+        for run_index in 0..runs {
+            if run_index > 0 {
+                info!("Resting for {:?} before the next run", rest);
+                thread::sleep(rest);
+            }
+            if runs > 1 && summary {
+                println!("Run {} of {}", run_index + 1, runs);
+            }
+
+            let mut profiler = Profiler::with_config(target, config.clone());
+            profiler.profile();
+            // each event already carries its URL, and stdout has to stay
+            // nothing but JSON lines.
+            if summary {
+                profiler.publish();
+            }
+
+            if let (Some(path), Some(jar)) = (cookie_jar_path, &profiler.cookie_jar) {
+                if let Err(e) = jar.save(path) {
+                    warn!("Could not save --cookie-jar {}: {}", path, e);
+                }
+            }
+
+            // the responses are gone, so there's nothing to pool - and there's
+            // only ever the one run to pool them over.
+            if config.streaming_stats {
+                return profiler.statistics();
+            }
+            run_means.extend(profiler.statistics().mean);
+            failed_responses.append(&mut profiler.failed_responses);
+            successful_responses.append(&mut profiler.successful_responses);
+        }
+
+        let statistics = Statistics::compute(target, &successful_responses, &failed_responses);
+        if runs > 1 && summary {
+            publish_across_runs(runs, &statistics, &run_means, unit);
+        }
+        return statistics;
+    };
+    // whichever way a target was profiled, its results go everywhere they've been asked for.
+    let run = |target: &Url, number_of_requests: i64| {
+        let statistics = profile_target(target, number_of_requests);
+        if let Some(collector) = statsd {
+            statsd::emit(collector, &statistics, target.host_str().unwrap_or(""));
+        }
+        if let Some(path) = dump_samples {
+            append_samples(path, &statistics.durations);
+        }
+        if let Some(template) = &template {
+            print!("{}", template.render(&statistics, unit));
+        }
+        return statistics;
+    };
+    let json_file = matches.value_of("JSON_FILE");
+    let html_file = matches.value_of("HTML");
+    let pretty = matches.is_present("PRETTY");
+    let fail_on_server_error = matches.is_present("FAIL_ON_SERVER_ERROR");
+    // only set alongside --min-samples, which clap makes sure of.
+    let required_samples = min_samples.filter(|_| matches.is_present("REQUIRE_MIN_SAMPLES"));
+    let mut assertions: Vec<Assertion> = match matches.values_of("ASSERT").map(|values| values.map(Assertion::parse).collect()) {
+        Some(Ok(assertions)) => assertions,
+        Some(Err(e)) => {
+            error!("Invalid --assert: {}", e);
+            process::exit(1);
+        }
+        None => Vec::new(),
+    };
+    // a streaming run only ever estimated these, so there's no answer for any other.
+    if let Some(assertion) = assertions.iter().find(|assertion| {
+        streaming_stats && assertion.percentile().is_some_and(|p| !STREAMING_QUANTILES.contains(&p))
+    }) {
+        error!(
+            "Invalid --assert: {} can't be checked with --streaming-stats, which only estimates {}",
+            assertion, STREAMING_QUANTILES.iter().map(|p| format!("p{}", p)).collect::<Vec<String>>().join(", ")
+        );
+        process::exit(1);
+    }
+    // the size gates are just assertions by another name, so they're
+    // reported and fail the run the same way.
+    for (name, assertion) in [("MAX_MEAN_SIZE", "mean_size"), ("MAX_SIZE", "max_size")].iter() {
+        if let Some(raw) = matches.value_of(name) {
+            match raw.parse::<u64>() {
+                Ok(bytes) => assertions.push(Assertion::parse(&format!("{}<={}", assertion, bytes)).unwrap()),
+                Err(_) => {
+                    error!("The value to --{} must be a number of bytes", name.to_ascii_lowercase().replace('_', "-"));
+                    process::exit(1);
+                }
+            }
+        }
+    }
+    let check_assertions = |statistics: &Statistics| {
+        let mut failed = 0;
+        for assertion in assertions.iter() {
+            let (passed, report) = assertion.check(statistics, unit);
+            // with `--jsonl`, stdout has to stay nothing but events.
+            match (passed, jsonl) {
+                (_, false) => println!("{}", report),
+                (true, true) => info!("{}", report),
+                (false, true) => warn!("{}", report),
+            }
+            if !passed {
+                failed += 1;
+            }
+        }
+        return failed;
+    };
+
+    // read targets from stdin either when asked to explicitly, or when no
+    // URL was given and something is being piped in to us.
+    let read_from_stdin = match matches.value_of("URL") {
+        Some(url) => url == "-",
+        None => !atty::is(atty::Stream::Stdin),
+    };
+
+    if !read_from_stdin {
+        let target = match matches.value_of("URL") {
+            Some(url) => parse_target(url, handshake_only),
+            None => {
+                error!("Please provide a URL with --url, or pipe URLs in on stdin");
+                process::exit(1);
+            }
+        };
+        let target = match target {
+            Ok(value) => value,
+            Err(e) => {
+                error!("{}", e);
+                process::exit(1);
+            }
+        };
+        if let Some(paths) = &sweep_paths {
+            sweep(&target, &config, paths);
+            return;
+        }
+        let statistics = run(&target, number_of_requests);
+        if let Some(path) = json_file {
+            write_json_file(path, &statistics.to_json(), pretty);
+        }
+        if let Some(path) = html_file {
+            write_html_file(path, std::slice::from_ref(&statistics), unit);
+        }
+        let failed_assertions = check_assertions(&statistics);
+        if fail_on_server_error {
+            exit_on_server_errors(statistics.server_errors());
+        }
+        if let Some(required) = required_samples {
+            exit_on_too_few_samples(usize::from(statistics.durations.len() < required), required);
+        }
+        exit_on_failed_assertions(failed_assertions);
+        return;
+    }
+
+    let mut all_statistics = Vec::new();
+    let mut reports = Vec::new();
+    let mut server_errors = 0;
+    let mut failed_assertions = 0;
+    let mut undersampled = 0;
+
+    let stdin = io::stdin();
+    for (index, line) in stdin.lock().lines().enumerate() {
+        let line = match line {
+            Ok(value) => value,
+            Err(e) => {
+                error!("Could not read from stdin: {}", e);
+                process::exit(1);
+            }
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // a bad line shouldn't take the rest of the stream down with it.
+        match parse_line(line, number_of_requests).and_then(|(url, count)| {
+            return parse_target(url, handshake_only).map(|target| (target, count));
+        }) {
+            Ok((target, count)) => {
+                if summary {
+                    println!("Profiling {}", target);
+                }
+                let statistics = run(&target, count);
+                server_errors += statistics.server_errors();
+                if required_samples.is_some_and(|required| statistics.durations.len() < required) {
+                    undersampled += 1;
+                }
+                failed_assertions += check_assertions(&statistics);
+                all_statistics.push(statistics.to_json());
+                if html_file.is_some() {
+                    reports.push(statistics);
+                }
+            }
+            Err(e) => warn!("Skipping line {} ({}): {}", index + 1, line, e),
+        }
+    }
+
+    if let Some(path) = json_file {
+        write_json_file(path, &Json::Array(all_statistics), pretty);
+    }
+    if let Some(path) = html_file {
+        write_html_file(path, &reports, unit);
+    }
+    if fail_on_server_error {
+        exit_on_server_errors(server_errors);
+    }
+    if let Some(required) = required_samples {
+        exit_on_too_few_samples(undersampled, required);
+    }
+    exit_on_failed_assertions(failed_assertions);
+}
+
+/* Every address we'd dial for `target`, or dies trying */
+fn resolve(target: &Url, config: &ProfilerConfig) -> Vec<SocketAddr> {
+    return match Profiler::with_config(target, config.clone()).resolve() {
+        Ok(addresses) => addresses,
         Err(e) => {
-            println!("Did not receive a valid URL: error was {}", e);
+            error!("Could not resolve {}: {}", target, e);
             process::exit(1);
         }
     };
-    if !vec!["http", "https"].contains(&target.scheme()) {
-        println!("We only support HTTP and HTTPS respectively");
+}
+
+/* For `--show-resolution`: every address we could dial for `target`, and
+   where they came from - so an override or a stale record shows up before
+   a single request goes out */
+fn show_resolution_for(target: &Url, config: &ProfilerConfig) {
+    let profiler = Profiler::with_config(target, config.clone());
+    let addresses = resolve(target, config);
+    println!(
+        "{}:{} resolved to {} address{}, from {}:",
+        target.host_str().unwrap_or(""), target.port_or_known_default().unwrap_or(80),
+        addresses.len(), if addresses.len() == 1 { "" } else { "es" }, profiler.resolution_source()
+    );
+    for (index, address) in addresses.iter().enumerate() {
+        match config.pinned_address {
+            Some(pinned) if pinned == *address => println!("  {}. {} (the only one dialed, for --address-index)", index, address),
+            _ => println!("  {}. {}", index, address),
+        }
+    }
+}
+
+/* Profiles each address `target` resolves to as if it were the only one,
+   then compares them - e.g. to pick out a slow backend behind round-robin DNS. */
+fn profile_each_address(target: &Url, config: &ProfilerConfig) -> Statistics {
+    let mut rows = Vec::new();
+    let mut successful_responses = Vec::new();
+    let mut failed_responses = Vec::new();
+
+    for address in resolve(target, config) {
+        if !config.jsonl {
+            println!("Profiling {} via {}", target, address);
+        }
+        let mut profiler = Profiler::with_config(target, config.clone().pinned_address(Some(address)));
+        profiler.profile();
+        if !config.jsonl {
+            profiler.publish();
+        }
+
+        let sorted_durations: Vec<Duration> = profiler.samples().into_iter().sorted().collect();
+        rows.push((address, profiler.statistics(), percentile(&sorted_durations, 99.0)));
+        failed_responses.append(&mut profiler.failed_responses);
+        successful_responses.append(&mut profiler.successful_responses);
+    }
+
+    if !config.jsonl {
+        let describe = |duration: Option<Duration>| duration.map_or(String::from("-"), |d| format_duration(config.unit, d));
+        println!("Latency by address:");
+        println!("  {:<40}  {:>10}  {:>14}  {:>14}", "address", "succeeded", "mean", "p99");
+        for (address, statistics, p99) in rows {
+            println!(
+                "  {:<40}  {:>9.1}%  {:>14}  {:>14}",
+                address.to_string(), statistics.percentage_succeeded, describe(statistics.mean), describe(p99)
+            );
+        }
+    }
+
+    return Statistics::compute(target, &successful_responses, &failed_responses);
+}
+
+/* For `--compare-schemes`: profiles `target` over plain http and over
+   https in turn, the same number of requests each, and puts what TLS adds
+   side by side - both in setting up connections, and in every request made
+   over one.
+
+   The scheme `target` doesn't have goes to `other_port`, or that scheme's
+   usual port. */
+fn compare_schemes_for(target: &Url, config: &ProfilerConfig, other_port: Option<u16>) -> Statistics {
+    let mut other = target.clone();
+    // both are special schemes, so switching between them can't fail.
+    let _ = other.set_scheme(if target.scheme() == "https" { "http" } else { "https" });
+    let _ = other.set_port(other_port);
+    let (plain, secure) = if target.scheme() == "https" { (other, target.clone()) } else { (target.clone(), other) };
+
+    let mut rows = Vec::new();
+    let mut successful_responses = Vec::new();
+    let mut failed_responses = Vec::new();
+    for url in [plain, secure].iter() {
+        if !config.jsonl {
+            println!("Profiling {}", url);
+        }
+        let mut profiler = Profiler::with_config(url, config.clone());
+        profiler.profile();
+        if !config.jsonl {
+            profiler.publish();
+        }
+
+        let sorted_durations: Vec<Duration> = profiler.samples().into_iter().sorted().collect();
+        // whatever a request spent connecting - and shaking hands - before
+        // it went out, averaged over every request.
+        let setup: Vec<Duration> = profiler.successful_responses.iter()
+            .map(|response| response.total_time.saturating_sub(response.time_taken))
+            .collect();
+        rows.push((url.scheme().to_string(), profiler.statistics(), percentile(&sorted_durations, 99.0), mean(&setup)));
+        failed_responses.append(&mut profiler.failed_responses);
+        successful_responses.append(&mut profiler.successful_responses);
+    }
+
+    if !config.jsonl {
+        let describe = |duration: Option<Duration>| duration.map_or(String::from("-"), |d| format_duration(config.unit, d));
+        println!("Latency by scheme ({} requests each):", config.number_of_requests);
+        println!("  {:<6}  {:>10}  {:>14}  {:>14}  {:>16}", "scheme", "succeeded", "mean", "p99", "connection setup");
+        for (scheme, statistics, p99, setup) in rows.iter() {
+            println!(
+                "  {:<6}  {:>9.1}%  {:>14}  {:>14}  {:>16}",
+                scheme, statistics.percentage_succeeded, describe(statistics.mean), describe(*p99), describe(*setup)
+            );
+        }
+
+        // a difference either way, as TLS can come out ahead on a noisy link.
+        let difference = |plain: Option<Duration>, secure: Option<Duration>| match (plain, secure) {
+            (Some(plain), Some(secure)) if secure >= plain => format!("+{}", format_duration(config.unit, secure - plain)),
+            (Some(plain), Some(secure)) => format!("-{}", format_duration(config.unit, plain - secure)),
+            _ => String::from("unknown"),
+        };
+        let ((_, plain, plain_p99, plain_setup), (_, secure, secure_p99, secure_setup)) = (&rows[0], &rows[1]);
+        println!(
+            "TLS cost: {} mean and {} p99 per request once connected, {} connection setup (handshake included) per request",
+            difference(plain.mean, secure.mean), difference(*plain_p99, *secure_p99), difference(*plain_setup, *secure_setup)
+        );
+    }
+
+    return Statistics::compute(target, &successful_responses, &failed_responses);
+}
+
+/* Profiles `target` at increasing levels of concurrency, from 1 up to the
+   configured maximum, printing a row of the load-vs-latency curve per level.
+   Every level makes the full `--profile` number of requests. */
+fn ramp(target: &Url, config: &ProfilerConfig, steps: usize) -> Statistics {
+    let maximum = config.concurrency;
+    let mut levels: Vec<usize> = (0..steps).map(|step| 1 + (maximum - 1) * step / (steps - 1)).collect();
+    levels.dedup();
+
+    if !config.jsonl {
+        println!("Concurrency ramp ({} requests per level):", config.number_of_requests);
+        println!("  {:>11}  {:>14}  {:>14}  {:>16}", "concurrency", "mean", "p99", "throughput");
+    }
+
+    let mut successful_responses = Vec::new();
+    let mut failed_responses = Vec::new();
+    for level in levels {
+        let mut profiler = Profiler::with_config(target, config.clone().concurrency(level));
+        profiler.profile();
+
+        if !config.jsonl {
+            let statistics = profiler.statistics();
+            let sorted_durations: Vec<Duration> = profiler.samples().into_iter().sorted().collect();
+            let describe = |duration: Option<Duration>| duration.map_or(String::from("-"), |d| format_duration(config.unit, d));
+            println!(
+                "  {:>11}  {:>14}  {:>14}  {:>14.1}/s",
+                level, describe(statistics.mean), describe(percentile(&sorted_durations, 99.0)), profiler.throughput()
+            );
+        }
+
+        failed_responses.append(&mut profiler.failed_responses);
+        successful_responses.append(&mut profiler.successful_responses);
+    }
+
+    return Statistics::compute(target, &successful_responses, &failed_responses);
+}
+
+/* Sums up a `--runs` profile once every run has been published on its own */
+fn publish_across_runs(runs: i64, statistics: &Statistics, run_means: &[Duration], unit: Option<TimeUnit>) {
+    println!("Across all {} runs:", runs);
+    println!("Number of requests: {}", statistics.total_requests);
+    println!("Percentage succeeded connecting: {}%", statistics.percentage_succeeded);
+    match statistics.mean {
+        Some(interval) => println!("Mean response time: {}", format_duration(unit, interval)),
+        None => println!("No mean response time recorded (no successful responses)")
+    }
+    match statistics.median {
+        Some(interval) => println!("Median response time: {}", format_duration(unit, interval)),
+        None => println!("No median response time recorded (no successful responses)")
+    }
+
+    // the spread of the per-run means is what says whether latency holds
+    // steady from one batch to the next.
+    if let (Some(deviation), Some(average)) = (standard_deviation(run_means), mean(run_means)) {
+        println!(
+            "Run-to-run standard deviation of the mean: {} ({:.1}% of the mean of run means, {})",
+            format_duration(unit, deviation),
+            deviation.as_secs_f64() / average.as_secs_f64() * 100_f64,
+            format_duration(unit, average)
+        );
+    }
+}
+
+/* The value of flag `name`, falling back on the `HTTP_PROFILER_<name>`
+   environment variable when it wasn't given - so CI can set defaults once
+   and still override them per invocation. */
+fn flag_or_env(matches: &clap::ArgMatches, name: &str) -> Option<String> {
+    return match matches.value_of(name) {
+        Some(value) => Some(value.to_string()),
+        None => env_default(name),
+    };
+}
+
+fn env_default(name: &str) -> Option<String> {
+    let variable = format!("HTTP_PROFILER_{}", name);
+    let value = env::var(&variable).ok().filter(|value| !value.is_empty())?;
+    debug!("Defaulting from {}={}", variable, value);
+
+    return Some(value);
+}
+
+/* Handles `--serve`, which turns us into a server with known behaviour to
+   point the profiler at. */
+fn run_server(port: &str, matches: &clap::ArgMatches) {
+    let port = match port.parse::<u16>() {
+        Ok(port) => port,
+        Err(_) => {
+            error!("The value to --serve must be a port number");
+            process::exit(1);
+        }
+    };
+    let status = match matches.value_of("SERVE_STATUS").unwrap_or("200").parse::<u16>() {
+        Ok(status) if (100..1000).contains(&status) => status,
+        _ => {
+            error!("The value to --serve-status must be a three digit status code");
+            process::exit(1);
+        }
+    };
+    let delay = match matches.value_of("SERVE_DELAY").unwrap_or("0").parse::<f64>() {
+        Ok(v) if v >= 0.0 && v.is_finite() => Duration::from_secs_f64(v / 1000_f64),
+        _ => {
+            error!("The value to --serve-delay must be a number of milliseconds, 0 or more");
+            process::exit(1);
+        }
+    };
+    let reply = serve::Reply{
+        status,
+        body: matches.value_of("SERVE_BODY").unwrap_or("ok").to_string(),
+        delay,
+    };
+
+    if let Err(e) = serve::serve(port, reply) {
+        error!("Could not serve on port {}: {}", port, e);
+        process::exit(1);
+    }
+}
+
+/* For `--sweep`: lists how every path answered, and exits with status 1 if
+   any of them is down - i.e. didn't answer, or answered with a 4xx or 5xx */
+fn sweep(target: &Url, config: &ProfilerConfig, paths: &[String]) {
+    let profiler = Profiler::with_config(target, config.clone());
+    let responses = match profiler.sweep(paths) {
+        Ok(responses) => responses,
+        Err(e) => {
+            error!("Could not sweep {}: {}", target, e);
+            process::exit(1);
+        }
+    };
+
+    println!("Health sweep of {} paths on {}:", paths.len(), target);
+    println!("  {:<40}  {:>6}  {:>14}", "path", "status", "latency");
+    let mut down = 0;
+    for (path, response) in paths.iter().zip(responses) {
+        match response {
+            Some(response) if (200..=399).contains(&response.status_code) => println!(
+                "  {:<40}  {}  {:>14}",
+                path, paint(format!("{:>6}", response.status_code), Color::Green), format_duration(config.unit, response.time_taken)
+            ),
+            Some(response) => {
+                down += 1;
+                println!(
+                    "  {:<40}  {}  {:>14}",
+                    path, paint(format!("{:>6}", response.status_code), Color::Red), format_duration(config.unit, response.time_taken)
+                );
+            }
+            None => {
+                down += 1;
+                println!("  {:<40}  {}  {:>14}", path, paint(format!("{:>6}", "-"), Color::Red), "no response");
+            }
+        }
+    }
+
+    if down > 0 {
+        error!("{} of {} paths are down", down, paths.len());
+        process::exit(1);
+    }
+}
+
+/* For `--fail-on-server-error`, once everything has been reported */
+fn exit_on_server_errors(server_errors: usize) {
+    if server_errors > 0 {
+        error!("{} responses had a 5xx status", server_errors);
         process::exit(1);
     }
+}
 
-    let mut profiler = Profiler::new(&target, number_of_requests);
-    profiler.profile();
-    profiler.publish();
+/* For `--require-min-samples`, once everything has been reported */
+fn exit_on_too_few_samples(undersampled: usize, required: usize) {
+    if undersampled > 0 {
+        error!("{} targets had fewer than the {} successful responses --min-samples asks for", undersampled, required);
+        process::exit(1);
+    }
+}
+
+/* For `--assert`, once everything has been reported */
+fn exit_on_failed_assertions(failed_assertions: usize) {
+    if failed_assertions > 0 {
+        error!("{} assertions failed", failed_assertions);
+        process::exit(1);
+    }
+}
+
+/* Adds `durations` to the end of the `--dump-samples` file, or dies trying */
+fn append_samples(path: &str, durations: &[Duration]) {
+    let contents: String = durations.iter().map(|duration| format!("{}\n", duration.as_micros())).collect();
+    let result = fs::OpenOptions::new().append(true).open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+    if let Err(e) = result {
+        error!("Could not write --dump-samples {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+/* Writes `json` out to `path`, or dies trying */
+fn write_json_file(path: &str, json: &Json, pretty: bool) {
+    let contents = if pretty { json.render_pretty() } else { json.render() };
+    if let Err(e) = write_atomically(path, &contents) {
+        error!("Could not write --json-file {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+/* Writes the `--html` report for `all_statistics` out to `path`, or dies trying */
+fn write_html_file(path: &str, all_statistics: &[Statistics], unit: Option<TimeUnit>) {
+    if let Err(e) = write_atomically(path, &html::render(all_statistics, unit)) {
+        error!("Could not write --html {}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+/* Writes to a temporary file beside `path`, then renames it into place - so
+   anything polling `path` only ever sees a complete file. */
+fn write_atomically(path: &str, contents: &str) -> io::Result<()> {
+    let temporary_path = format!("{}.tmp.{}", path, process::id());
+    let result = fs::File::create(&temporary_path).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        return fs::rename(&temporary_path, path);
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+    }
+
+    return result;
+}
+
+/* Parses a human-sized duration: a number followed by ms, s, m or h, with
+   a bare number taken as seconds. */
+fn parse_duration(raw: &str) -> Option<Duration> {
+    let raw = raw.trim();
+    let split = raw.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(raw.len());
+    let (number, unit) = raw.split_at(split);
+    let number = number.parse::<f64>().ok().filter(|number| number.is_finite())?;
+    let seconds = match unit {
+        "ms" => number / 1000_f64,
+        "" | "s" => number,
+        "m" => number * 60_f64,
+        "h" => number * 3600_f64,
+        _ => return None,
+    };
+
+    return Some(Duration::from_secs_f64(seconds));
+}
+
+/* Parses a `--resolve` mapping, as curl has it: `host:port:addr[,addr...]`,
+   with any IPv6 address in brackets. */
+fn parse_resolve(raw: &str) -> Result<(String, u16, Vec<IpAddr>), String> {
+    let mut fields = raw.splitn(3, ':');
+    let (host, port, addresses) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(host), Some(port), Some(addresses)) if !host.is_empty() => (host, port, addresses),
+        _ => return Err(String::from("expected host:port:address")),
+    };
+    let port = port.parse::<u16>().map_err(|_| format!("{:?} isn't a port", port))?;
+    let addresses = addresses.split(',')
+        .map(|address| {
+            let address = address.trim().trim_start_matches('[').trim_end_matches(']');
+            return address.parse::<IpAddr>().map_err(|_| format!("{:?} isn't an IP address", address));
+        })
+        .collect::<Result<Vec<IpAddr>, String>>()?;
+
+    return Ok((host.to_ascii_lowercase(), port, addresses));
+}
+
+/* Splits a line of URL input into the URL and how many requests to make to
+   it - either the count after the URL, e.g. `https://host/path 500`, or
+   `default_count` if there's none. */
+fn parse_line(line: &str, default_count: i64) -> Result<(&str, i64), String> {
+    let mut fields = line.split_whitespace();
+    // callers skip blank lines, so there's always a first field.
+    let url = fields.next().unwrap_or("");
+    let count = match fields.next() {
+        Some(raw) => match raw.parse::<i64>() {
+            Ok(count) if count > 0 => count,
+            _ => return Err(format!("the request count {:?} must be a positive number", raw)),
+        },
+        None => default_count,
+    };
+    if fields.next().is_some() {
+        return Err(String::from("expected a URL, optionally followed by a request count"));
+    }
+
+    return Ok((url, count));
+}
+
+/* Parses and validates a single URL we've been asked to profile */
+fn parse_target(raw: &str, handshake_only: bool) -> Result<Url, String> {
+    let target = match Url::parse(raw) {
+        Ok(value) => value,
+        Err(e) => return Err(format!("Did not receive a valid URL: error was {}", e)),
+    };
+    if !["http", "https"].contains(&target.scheme()) {
+        return Err(String::from("We only support HTTP and HTTPS respectively"));
+    }
+    if handshake_only && target.scheme() != "https" {
+        return Err(String::from("--handshake-only needs an https URL to shake hands with"));
+    }
 
-}
\ No newline at end of file
+    return Ok(target);
+}
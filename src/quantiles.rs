@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// the quantiles a streaming run keeps track of.
+pub const STREAMING_QUANTILES: [f64; 3] = [50.0, 95.0, 99.0];
+
+/* An estimate of one quantile over a stream of values, in constant memory,
+   after Jain and Chlamtac's P² algorithm ("The P² algorithm for dynamic
+   calculation of quantiles and histograms without storing observations",
+   1985).
+
+   It keeps five markers - the minimum, the maximum, the quantile itself and
+   one either side of it halfway to each end - and nudges their heights along
+   a parabola through their neighbours as each value comes in. Until there
+   are five values, it simply has them all, and the answer is exact. */
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    // as a fraction, e.g. 0.99.
+    quantile: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired_positions: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(percentile: f64) -> P2Quantile {
+        let p = percentile / 100.0;
+        return P2Quantile{
+            quantile: p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired_positions: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        };
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = value;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            }
+            return;
+        }
+        self.count += 1;
+
+        // which pair of markers the value falls between, stretching the
+        // ends to take it if it's a new extreme.
+        let cell = if value < self.heights[0] {
+            self.heights[0] = value;
+            0
+        } else if value >= self.heights[4] {
+            self.heights[4] = value;
+            3
+        } else {
+            (1..5).find(|i| value < self.heights[*i]).unwrap() - 1
+        };
+        for position in self.positions[cell + 1..].iter_mut() {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired_positions.iter_mut().zip(self.increments.iter()) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let offset = self.desired_positions[i] - self.positions[i];
+            let room_above = self.positions[i + 1] - self.positions[i] > 1.0;
+            let room_below = self.positions[i - 1] - self.positions[i] < -1.0;
+            if (offset >= 1.0 && room_above) || (offset <= -1.0 && room_below) {
+                let step = offset.signum();
+                let parabolic = self.parabolic(i, step);
+                self.heights[i] = match parabolic > self.heights[i - 1] && parabolic < self.heights[i + 1] {
+                    true => parabolic,
+                    // the parabola overshot a neighbour, so fall back to a straight line.
+                    false => self.linear(i, step),
+                };
+                self.positions[i] += step;
+            }
+        }
+    }
+
+    fn parabolic(&self, i: usize, step: f64) -> f64 {
+        let (q, n) = (&self.heights, &self.positions);
+        return q[i] + step / (n[i + 1] - n[i - 1]) * (
+            (n[i] - n[i - 1] + step) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                + (n[i + 1] - n[i] - step) * (q[i] - q[i - 1]) / (n[i] - n[i - 1])
+        );
+    }
+
+    fn linear(&self, i: usize, step: f64) -> f64 {
+        let neighbour = if step > 0.0 { i + 1 } else { i - 1 };
+        return self.heights[i] + step * (self.heights[neighbour] - self.heights[i]) / (self.positions[neighbour] - self.positions[i]);
+    }
+
+    /* The estimate so far, or None before the first value */
+    pub fn estimate(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        if self.count < 5 {
+            // nearest rank over what we have, as `statistics::percentile` does.
+            let mut values = self.heights[..self.count].to_vec();
+            values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let rank = (self.quantile * self.count as f64).ceil().max(1.0) as usize;
+            return Some(values[rank - 1]);
+        }
+
+        return Some(self.heights[2]);
+    }
+}
+
+/* For `--streaming-stats`: all a run keeps of its successful responses, in
+   place of the responses themselves - so memory stays the same whether it
+   makes a hundred requests or a hundred million.
+
+   Counts, extremes and the mean are exact; the quantiles are P² estimates. */
+#[derive(Debug, Clone)]
+pub struct StreamingStats {
+    pub count: usize,
+    // nanoseconds, as u128 so it can't overflow this side of the heat death.
+    total_latency: u128,
+    pub fastest: Option<Duration>,
+    pub slowest: Option<Duration>,
+    quantiles: Vec<P2Quantile>,
+    // there are only so many status codes, so this stays small.
+    pub status_codes: BTreeMap<i32, usize>,
+    pub incomplete: usize,
+    pub smallest_size: Option<usize>,
+    pub largest_size: Option<usize>,
+    total_size: u128,
+}
+
+impl Default for StreamingStats {
+    fn default() -> StreamingStats {
+        return StreamingStats::new();
+    }
+}
+
+impl StreamingStats {
+    pub fn new() -> StreamingStats {
+        return StreamingStats{
+            count: 0,
+            total_latency: 0,
+            fastest: None,
+            slowest: None,
+            quantiles: STREAMING_QUANTILES.iter().map(|p| P2Quantile::new(*p)).collect(),
+            status_codes: BTreeMap::new(),
+            incomplete: 0,
+            smallest_size: None,
+            largest_size: None,
+            total_size: 0,
+        };
+    }
+
+    pub fn record(&mut self, latency: Duration, status_code: i32, body_size: usize, incomplete: bool) {
+        self.count += 1;
+        self.total_latency += latency.as_nanos();
+        self.fastest = Some(self.fastest.map_or(latency, |fastest| fastest.min(latency)));
+        self.slowest = Some(self.slowest.map_or(latency, |slowest| slowest.max(latency)));
+        for quantile in self.quantiles.iter_mut() {
+            quantile.observe(latency.as_nanos() as f64);
+        }
+        *self.status_codes.entry(status_code).or_default() += 1;
+        if incomplete {
+            self.incomplete += 1;
+        }
+        self.smallest_size = Some(self.smallest_size.map_or(body_size, |smallest| smallest.min(body_size)));
+        self.largest_size = Some(self.largest_size.map_or(body_size, |largest| largest.max(body_size)));
+        self.total_size += body_size as u128;
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        return Some(Duration::from_nanos((self.total_latency / self.count as u128) as u64));
+    }
+
+    pub fn mean_size(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        return Some(self.total_size as f64 / self.count as f64);
+    }
+
+    /* The estimate for one of STREAMING_QUANTILES */
+    pub fn quantile(&self, percentile: f64) -> Option<Duration> {
+        let index = STREAMING_QUANTILES.iter().position(|p| *p == percentile)?;
+
+        // the estimate lies between two values we've seen, so it can't be negative.
+        return self.quantiles[index].estimate().map(|nanoseconds| Duration::from_nanos(nanoseconds.round() as u64));
+    }
+}
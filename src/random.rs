@@ -0,0 +1,48 @@
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/* A small, fast, seedable source of randomness - xorshift64*, which is plenty
+   for spreading requests out and nowhere near good enough for anything secret.
+
+   The same seed gives the same sequence every time, so a run can be replayed
+   exactly by passing the seed back in. */
+#[derive(Debug, Clone)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Xorshift {
+        // all zeroes is the one state xorshift can never leave, so nudge it
+        // somewhere else.
+        return Xorshift{ state: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed } };
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return self.state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+    }
+
+    /* Uniformly in [0, 1), from the top 53 bits - all a double can hold */
+    pub fn next_f64(&mut self) -> f64 {
+        return (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    }
+
+    /* Uniformly between zero and `limit`, to the nanosecond */
+    pub fn duration_below(&mut self, limit: Duration) -> Duration {
+        let nanos = limit.as_nanos().min(u64::MAX as u128) as u64;
+        if nanos == 0 {
+            return Duration::from_nanos(0);
+        }
+
+        return Duration::from_nanos(self.next_u64() % nanos);
+    }
+}
+
+/* A seed for when none was asked for, different from run to run */
+pub fn fresh_seed() -> u64 {
+    let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    return (since.as_secs() << 30) ^ since.subsec_nanos() as u64 ^ ((process::id() as u64) << 48);
+}
@@ -0,0 +1,146 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+/* What `--serve` answers every request with */
+#[derive(Debug, Clone)]
+pub struct Reply {
+    pub status: u16,
+    pub body: String,
+    // how long to sit on each request before answering it.
+    pub delay: Duration,
+}
+
+/* Runs a trivial HTTP/1.1 server on localhost, forever.
+
+   Every request gets the same reply, which makes it a target with known
+   behaviour for checking our own measurements against. */
+pub fn serve(port: u16, reply: Reply) -> io::Result<()> {
+    return serve_on(TcpListener::bind(("127.0.0.1", port))?, reply);
+}
+
+/* Serves on a listener that's already bound - port 0's, say, for a test */
+pub fn serve_on(listener: TcpListener, reply: Reply) -> io::Result<()> {
+    info!("Serving {} responses on http://{}/", reply.status, listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Could not accept a connection: {}", e);
+                continue;
+            }
+        };
+        let reply = reply.clone();
+        thread::spawn(move || {
+            if let Err(e) = answer(stream, &reply) {
+                debug!("Connection ended with an error: {}", e);
+            }
+        });
+    }
+
+    return Ok(());
+}
+
+/* Answers requests on one connection until the client is done with it */
+fn answer(stream: TcpStream, reply: &Reply) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line)? == 0 {
+            return Ok(());
+        }
+
+        let mut content_length = 0;
+        let mut close = request_line.trim_end().ends_with("HTTP/1.0");
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                let value = value.trim();
+                if key.eq_ignore_ascii_case("Content-Length") {
+                    content_length = value.parse::<u64>().unwrap_or(0);
+                } else if key.eq_ignore_ascii_case("Connection") {
+                    close = value.eq_ignore_ascii_case("close");
+                }
+            }
+        }
+        // whatever was sent, we don't need it - but it has to be read past.
+        io::copy(&mut (&mut reader).take(content_length), &mut io::sink())?;
+
+        thread::sleep(reply.delay);
+        // these can't carry a body, so there'd be no telling where it ended.
+        let no_body = request_line.starts_with("HEAD ") || reply.status == 204 || reply.status == 304;
+        let response = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: {}\r\n\r\n{}",
+            reply.status,
+            reason_phrase(reply.status),
+            if no_body { 0 } else { reply.body.len() },
+            if close { "close" } else { "keep-alive" },
+            if no_body { "" } else { reply.body.as_str() }
+        );
+        writer.write_all(response.as_bytes())?;
+        writer.flush()?;
+
+        if close {
+            return Ok(());
+        }
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    return match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        _ => "Unknown",
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProfilerConfig;
+    use crate::connect::{Profiler, StatusClass};
+    use url::Url;
+
+    #[test]
+    fn profiling_the_mock_server_gets_back_what_it_serves() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let reply = Reply{ status: 503, body: String::from("down"), delay: Duration::from_millis(20) };
+        // it serves forever, so it's left to go down with the test.
+        thread::spawn(move || serve_on(listener, reply));
+
+        let mut profiler = Profiler::with_config(&target, ProfilerConfig::new(3).keep_alive(true));
+        profiler.profile();
+        let statistics = profiler.statistics();
+
+        assert_eq!(statistics.successful_requests, 3);
+        assert_eq!(statistics.class_counts[&StatusClass::ServerError], 3);
+        assert_eq!(statistics.smallest_size, Some(4));
+        // the delay is a floor under every response.
+        assert!(statistics.fastest.unwrap() >= Duration::from_millis(20));
+        assert_eq!(profiler.reconnections, 0);
+    }
+}
@@ -0,0 +1,161 @@
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sha::sha256;
+use openssl::sign::Signer;
+use std::env;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+/* Signs requests with AWS Signature Version 4, for `--aws-sigv4 region/service`.
+
+   The credentials come from the usual environment variables, as the AWS CLI
+   reads them. Signatures cover the method, path, query, body, `Host` and the
+   time, and are only good for a few minutes - so every request gets its own. */
+#[derive(Clone)]
+pub struct SigV4 {
+    region: String,
+    service: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+// the secret key has no business turning up in debug logs.
+impl fmt::Debug for SigV4 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "SigV4 {{ region: {:?}, service: {:?}, access_key: {:?} }}", self.region, self.service, self.access_key);
+    }
+}
+
+impl SigV4 {
+    /* Takes `region/service`, e.g. `us-east-1/execute-api` */
+    pub fn from_env(scope: &str) -> Result<SigV4, String> {
+        let (region, service) = match scope.split_once('/') {
+            Some((region, service)) if !region.is_empty() && !service.is_empty() && !service.contains('/') => (region, service),
+            _ => return Err(format!("{:?} is not of the form region/service, e.g. us-east-1/execute-api", scope)),
+        };
+        let variable = |name: &str| env::var(name).ok().filter(|value| !value.is_empty());
+
+        return Ok(SigV4{
+            region: region.to_string(),
+            service: service.to_string(),
+            access_key: variable("AWS_ACCESS_KEY_ID").ok_or("AWS_ACCESS_KEY_ID is not set")?,
+            secret_key: variable("AWS_SECRET_ACCESS_KEY").ok_or("AWS_SECRET_ACCESS_KEY is not set")?,
+            session_token: variable("AWS_SESSION_TOKEN"),
+        });
+    }
+
+    /* The headers to add to a request for it to be signed as of `now` -
+       `Authorization` and the `X-Amz-*` ones it covers */
+    pub fn sign(&self, method: &str, target: &Url, body: &[u8], now: SystemTime) -> Vec<(String, String)> {
+        let timestamp = amz_date(now);
+        let date = &timestamp[..8];
+        let body_hash = hex(&sha256(body));
+
+        // every header we sign, lowercased and in order - which these
+        // happen to be already.
+        let mut signed = vec![
+            (String::from("host"), target.host_str().unwrap_or("").to_string()),
+            (String::from("x-amz-content-sha256"), body_hash.clone()),
+            (String::from("x-amz-date"), timestamp.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            signed.push((String::from("x-amz-security-token"), token.clone()));
+        }
+        let signed_headers = signed.iter().map(|(key, _)| key.as_str()).collect::<Vec<&str>>().join(";");
+
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            self.canonical_path(target),
+            canonical_query(target),
+            signed.iter().map(|(key, value)| format!("{}:{}\n", key, value.trim())).collect::<String>(),
+            signed_headers,
+            body_hash
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, self.service);
+        let string_to_sign = format!("AWS4-HMAC-SHA256\n{}\n{}\n{}", timestamp, scope, hex(&sha256(canonical_request.as_bytes())));
+
+        let key = [date, self.region.as_str(), self.service.as_str(), "aws4_request"].iter()
+            .fold(format!("AWS4{}", self.secret_key).into_bytes(), |key, part| hmac(&key, part.as_bytes()));
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        let mut headers = vec![(
+            String::from("Authorization"),
+            format!("AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}", self.access_key, scope, signed_headers, signature),
+        )];
+        // `Host` is already sent regardless.
+        headers.extend(signed.into_iter().skip(1).map(|(key, value)| (amz_header_name(&key), value)));
+
+        return headers;
+    }
+
+    /* S3 takes the path as it is; everything else has it encoded once more */
+    fn canonical_path(&self, target: &Url) -> String {
+        if self.service == "s3" {
+            return target.path().to_string();
+        }
+
+        return target.path().split('/').map(encode).collect::<Vec<String>>().join("/");
+    }
+}
+
+fn canonical_query(target: &Url) -> String {
+    let mut pairs: Vec<(String, String)> = target.query_pairs().map(|(key, value)| (encode(&key), encode(&value))).collect();
+    pairs.sort();
+
+    return pairs.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<String>>().join("&");
+}
+
+/* Percent-encodes all but the unreserved characters, the way SigV4 wants */
+fn encode(raw: &str) -> String {
+    return raw.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect();
+}
+
+fn amz_header_name(lowercase: &str) -> String {
+    return match lowercase {
+        "x-amz-content-sha256" => String::from("X-Amz-Content-Sha256"),
+        "x-amz-date" => String::from("X-Amz-Date"),
+        "x-amz-security-token" => String::from("X-Amz-Security-Token"),
+        other => other.to_string(),
+    };
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // neither of these can fail for HMAC-SHA256 with a key in memory.
+    let key = PKey::hmac(key).unwrap();
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+    signer.update(data).unwrap();
+
+    return signer.sign_to_vec().unwrap();
+}
+
+fn hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+}
+
+/* `now` as `YYYYMMDDTHHMMSSZ`, in UTC */
+fn amz_date(now: SystemTime) -> String {
+    let seconds = now.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+    let (days, time_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // days since the epoch to a civil date, after Howard Hinnant's algorithm.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year, month, day, time_of_day / 3600, time_of_day % 3600 / 60, time_of_day % 60
+    );
+}
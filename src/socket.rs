@@ -0,0 +1,75 @@
+use std::io;
+use std::mem;
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::time::Duration;
+
+/* Like TcpStream::connect_timeout, but from a particular local address.
+
+   std has no way to bind a socket before connecting it, so this goes through
+   libc for the socket, bind and connect calls and hands the result back as a
+   plain TcpStream. */
+pub fn connect_from(local: IpAddr, remote: &SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    if local.is_ipv4() != remote.is_ipv4() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cannot reach {} from {}, the address families differ", remote, local),
+        ));
+    }
+
+    let family = if remote.is_ipv4() { libc::AF_INET } else { libc::AF_INET6 };
+    let fd = unsafe { libc::socket(family, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // owning the descriptor straight away means it's closed on every early return.
+    let stream = unsafe { TcpStream::from_raw_fd(fd) };
+
+    let (address, length) = socket_address(&SocketAddr::new(local, 0));
+    if unsafe { libc::bind(fd, &address as *const _ as *const libc::sockaddr, length) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Linux bounds a blocking connect by the send timeout, which saves us
+    // juggling a non-blocking socket and poll.
+    stream.set_write_timeout(Some(timeout))?;
+    let (address, length) = socket_address(remote);
+    if unsafe { libc::connect(stream.as_raw_fd(), &address as *const _ as *const libc::sockaddr, length) } < 0 {
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() == Some(libc::EINPROGRESS) {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "connection timed out"));
+        }
+        return Err(error);
+    }
+
+    return Ok(stream);
+}
+
+fn socket_address(address: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let length = match address {
+        SocketAddr::V4(v4) => {
+            let raw = libc::sockaddr_in {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: v4.port().to_be(),
+                sin_addr: libc::in_addr { s_addr: u32::from_ne_bytes(v4.ip().octets()) },
+                sin_zero: [0; 8],
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in).write(raw) };
+            mem::size_of::<libc::sockaddr_in>()
+        }
+        SocketAddr::V6(v6) => {
+            let raw = libc::sockaddr_in6 {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: v6.port().to_be(),
+                sin6_flowinfo: v6.flowinfo(),
+                sin6_addr: libc::in6_addr { s6_addr: v6.ip().octets() },
+                sin6_scope_id: v6.scope_id(),
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in6).write(raw) };
+            mem::size_of::<libc::sockaddr_in6>()
+        }
+    };
+
+    return (storage, length as libc::socklen_t);
+}
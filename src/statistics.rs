@@ -0,0 +1,331 @@
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::time::Duration;
+use url::Url;
+
+use crate::connect::{FailureCategory, ResponseProperties, StatusClass};
+use crate::json::Json;
+use crate::quantiles::{StreamingStats, STREAMING_QUANTILES};
+
+// goes up whenever a field of `to_json` is added, removed or changes meaning,
+// so that whatever reads the output can tell which shape it's getting.
+pub const SCHEMA_VERSION: usize = 2;
+
+const STATUS_BUCKETS: [&str; 5] = ["1xx", "2xx", "3xx", "4xx", "5xx"];
+
+/* Summary figures for one profiling run.
+
+   Worked out once, so that every way we report a run - the terminal summary,
+   `--json-file` and so on - agrees on the numbers. */
+#[derive(Debug, Clone)]
+pub struct Statistics {
+    pub url: String,
+    pub total_requests: usize,
+    pub successful_requests: usize,
+    pub failed_requests: usize,
+    // how many failures fell in each category, with the first one's message
+    // as an example. Every category is present, like `class_counts`.
+    pub failures: BTreeMap<FailureCategory, (usize, Option<String>)>,
+    // counted among the successful ones, as we did get something back.
+    pub incomplete_responses: usize,
+    pub percentage_succeeded: f64,
+    pub percentage_non_200: f64,
+    pub non_200_status_codes: BTreeSet<i32>,
+    // every class is present, even with a count of 0.
+    pub class_counts: BTreeMap<StatusClass, usize>,
+    // keyed "1xx" to "5xx", all present, plus "other" for anything outside them.
+    pub status_buckets: BTreeMap<&'static str, usize>,
+    pub fastest: Option<Duration>,
+    pub mean: Option<Duration>,
+    // half the width of the 95% confidence interval around `mean`.
+    pub mean_margin: Option<Duration>,
+    pub median: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub slowest: Option<Duration>,
+    pub smallest_size: Option<usize>,
+    pub largest_size: Option<usize>,
+    pub mean_size: Option<f64>,
+    // how many different bodies came back, going by their hashes.
+    pub distinct_bodies: usize,
+    // every successful response's latency in the order they completed, and
+    // again fastest first, for anything the figures above don't cover.
+    pub durations: Vec<Duration>,
+    pub sorted_durations: Vec<Duration>,
+    // for `--streaming-stats`, which has no durations to go by: the
+    // percentiles it estimated instead, e.g. (95.0, p95).
+    pub estimated_percentiles: Vec<(f64, Duration)>,
+}
+
+impl Statistics {
+    pub fn compute(
+        target: &Url,
+        successful_responses: &[ResponseProperties],
+        failed_responses: &[Box<dyn Error + Send + Sync>],
+    ) -> Statistics {
+        let successful_requests = successful_responses.len();
+        let failed_requests = failed_responses.len();
+        let total_requests = successful_requests + failed_requests;
+
+        let unsuccessful_status_codes: Vec<i32> = successful_responses.iter()
+            .filter(|&i| i.status_code != 200).map(|i| i.status_code).collect();
+
+        let mut class_counts: BTreeMap<StatusClass, usize> = [
+            StatusClass::Success, StatusClass::Redirect, StatusClass::ClientError, StatusClass::ServerError, StatusClass::Other,
+        ].iter().map(|class| (*class, 0)).collect();
+        for response in successful_responses.iter() {
+            *class_counts.entry(response.classification).or_default() += 1;
+        }
+
+        let mut failures: BTreeMap<FailureCategory, (usize, Option<String>)> = FailureCategory::ALL.iter()
+            .map(|category| (*category, (0, None))).collect();
+        for failure in failed_responses.iter() {
+            let (count, sample) = failures.entry(FailureCategory::of(failure.as_ref())).or_default();
+            *count += 1;
+            sample.get_or_insert_with(|| failure.to_string());
+        }
+
+        let mut status_buckets: BTreeMap<&'static str, usize> = STATUS_BUCKETS.iter().map(|bucket| (*bucket, 0)).collect();
+        for response in successful_responses.iter() {
+            *status_buckets.entry(status_bucket(response.status_code)).or_default() += 1;
+        }
+
+        let durations: Vec<Duration> = successful_responses.iter().map(|i| i.time_taken).collect();
+        let sorted_durations = durations.iter().cloned().sorted().collect::<Vec<Duration>>();
+        let sizes: Vec<usize> = successful_responses.iter().map(|i| i.body_size).collect();
+
+        return Statistics{
+            url: target.to_string(),
+            total_requests,
+            successful_requests,
+            failed_requests,
+            failures,
+            incomplete_responses: successful_responses.iter().filter(|i| i.incomplete).count(),
+            percentage_succeeded: successful_requests as f64 / total_requests as f64 * 100_f64,
+            percentage_non_200: unsuccessful_status_codes.len() as f64 / successful_requests as f64 * 100_f64,
+            non_200_status_codes: unsuccessful_status_codes.into_iter().collect(),
+            class_counts,
+            status_buckets,
+            fastest: sorted_durations.first().cloned(),
+            mean: mean(&durations),
+            mean_margin: confidence_margin(&durations),
+            median: median(&sorted_durations),
+            p99: percentile(&sorted_durations, 99.0),
+            slowest: sorted_durations.last().cloned(),
+            smallest_size: sizes.iter().min().cloned(),
+            largest_size: sizes.iter().max().cloned(),
+            mean_size: Some(sizes.iter().sum::<usize>() as f64 / sizes.len() as f64).filter(|size| size.is_finite()),
+            distinct_bodies: successful_responses.iter().map(|i| i.body_hash).collect::<BTreeSet<u64>>().len(),
+            durations,
+            sorted_durations,
+            estimated_percentiles: Vec::new(),
+        };
+    }
+
+    /* For `--streaming-stats`, where the responses are long gone: the same
+       figures from what was kept of them instead. The median and p99 are
+       estimates, as are `estimated_percentiles`; there's no confidence interval and no count of distinct
+       bodies; and `durations` are left empty. */
+    pub fn from_streaming(
+        target: &Url,
+        streaming: &StreamingStats,
+        failed_responses: &[Box<dyn Error + Send + Sync>],
+    ) -> Statistics {
+        let mut statistics = Statistics::compute(target, &[], failed_responses);
+        let successful_requests = streaming.count;
+        statistics.total_requests += successful_requests;
+        statistics.successful_requests = successful_requests;
+        statistics.percentage_succeeded = successful_requests as f64 / statistics.total_requests as f64 * 100_f64;
+
+        let non_200: usize = streaming.status_codes.iter().filter(|(code, _)| **code != 200).map(|(_, count)| *count).sum();
+        statistics.percentage_non_200 = non_200 as f64 / successful_requests as f64 * 100_f64;
+        statistics.non_200_status_codes = streaming.status_codes.keys().filter(|code| **code != 200).cloned().collect();
+        for (code, count) in streaming.status_codes.iter() {
+            *statistics.class_counts.entry(StatusClass::from_status(*code)).or_default() += count;
+            *statistics.status_buckets.entry(status_bucket(*code)).or_default() += count;
+        }
+
+        statistics.incomplete_responses = streaming.incomplete;
+        statistics.fastest = streaming.fastest;
+        statistics.mean = streaming.mean();
+        statistics.median = streaming.quantile(50.0);
+        statistics.p99 = streaming.quantile(99.0);
+        statistics.slowest = streaming.slowest;
+        statistics.smallest_size = streaming.smallest_size;
+        statistics.largest_size = streaming.largest_size;
+        statistics.mean_size = streaming.mean_size();
+        statistics.estimated_percentiles = STREAMING_QUANTILES.iter()
+            .filter_map(|p| streaming.quantile(*p).map(|estimate| (*p, estimate)))
+            .collect();
+
+        return statistics;
+    }
+
+    pub fn server_errors(&self) -> usize {
+        return self.class_counts.get(&StatusClass::ServerError).cloned().unwrap_or(0);
+    }
+
+    pub fn to_json(&self) -> Json {
+        return Json::object(vec![
+            ("schema_version", SCHEMA_VERSION.into()),
+            ("url", self.url.as_str().into()),
+            ("total_requests", self.total_requests.into()),
+            ("successful_requests", self.successful_requests.into()),
+            ("failed_requests", self.failed_requests.into()),
+            ("failures", Json::object(
+                self.failures.iter().map(|(category, (count, sample))| (category.label(), Json::object(vec![
+                    ("count", (*count).into()),
+                    ("sample", sample.clone().into()),
+                ]))).collect()
+            )),
+            ("incomplete_responses", self.incomplete_responses.into()),
+            ("percentage_succeeded", self.percentage_succeeded.into()),
+            ("percentage_non_200", self.percentage_non_200.into()),
+            ("non_200_status_codes", self.non_200_status_codes.iter().cloned().collect::<Vec<i32>>().into()),
+            ("responses_by_class", Json::object(
+                self.class_counts.iter().map(|(class, count)| (class.label(), (*count).into())).collect()
+            )),
+            ("responses_by_status_bucket", Json::object(
+                self.status_buckets.iter().map(|(bucket, count)| (*bucket, (*count).into())).collect()
+            )),
+            ("fastest_ms", self.fastest.map(milliseconds).into()),
+            ("mean_ms", self.mean.map(milliseconds).into()),
+            ("mean_ci95_margin_ms", self.mean_margin.map(milliseconds).into()),
+            ("median_ms", self.median.map(milliseconds).into()),
+            ("p99_ms", self.p99.map(milliseconds).into()),
+            ("slowest_ms", self.slowest.map(milliseconds).into()),
+            ("smallest_size_bytes", self.smallest_size.into()),
+            ("largest_size_bytes", self.largest_size.into()),
+            ("mean_size_bytes", self.mean_size.into()),
+            ("distinct_bodies", self.distinct_bodies.into()),
+        ]);
+    }
+}
+
+fn status_bucket(status_code: i32) -> &'static str {
+    return match status_code {
+        100..=599 => STATUS_BUCKETS[(status_code / 100 - 1) as usize],
+        _ => "other",
+    };
+}
+
+pub fn milliseconds(duration: Duration) -> f64 {
+    return duration.as_nanos() as f64 / 1e6;
+}
+
+/* Summed as u128 nanoseconds rather than as a Duration, which panics on
+   overflow, and divided as one - a u32 count would wrap past 4 billion samples */
+pub fn mean(durations: &[Duration]) -> Option<Duration> {
+    if durations.is_empty() {
+        return None;
+    }
+    let nanoseconds = durations.iter().map(|i| i.as_nanos()).sum::<u128>() / durations.len() as u128;
+
+    return Some(Duration::new((nanoseconds / 1_000_000_000) as u64, (nanoseconds % 1_000_000_000) as u32));
+}
+
+/* The mean latency with each response counting once per byte of body, so
+   that a few large responses weigh as much as the bytes they carried. None
+   if there were no bytes at all to weigh by. */
+pub fn size_weighted_mean(responses: &[ResponseProperties]) -> Option<Duration> {
+    let bytes = responses.iter().map(|i| i.body_size as u128).sum::<u128>();
+    if bytes == 0 {
+        return None;
+    }
+    let nanoseconds = responses.iter().map(|i| i.time_taken.as_nanos() * i.body_size as u128).sum::<u128>() / bytes;
+
+    return Some(Duration::new((nanoseconds / 1_000_000_000) as u64, (nanoseconds % 1_000_000_000) as u32));
+}
+
+pub fn median(sorted_durations: &[Duration]) -> Option<Duration> {
+    let x = sorted_durations.len();
+    if x == 0 {
+        return None;
+    }
+    if x % 2 == 1 {
+        return Some(sorted_durations[x / 2]);
+    }
+
+    return (sorted_durations[x / 2 - 1] + sorted_durations[x / 2]).checked_div(2);
+}
+
+/* Population standard deviation */
+pub fn standard_deviation(durations: &[Duration]) -> Option<Duration> {
+    let average = mean(durations)?.as_secs_f64();
+    let variance = durations.iter()
+        .map(|i| (i.as_secs_f64() - average).powi(2))
+        .sum::<f64>() / durations.len() as f64;
+
+    return Some(Duration::from_secs_f64(variance.sqrt()));
+}
+
+/* Half-width of the 95% confidence interval for the mean of `durations`,
+   from Student's t-distribution and the sample standard deviation.
+
+   There's no interval to speak of with fewer than two samples, so that's None. */
+pub fn confidence_margin(durations: &[Duration]) -> Option<Duration> {
+    let n = durations.len();
+    if n < 2 {
+        return None;
+    }
+    let average = mean(durations)?.as_secs_f64();
+    let sample_variance = durations.iter()
+        .map(|i| (i.as_secs_f64() - average).powi(2))
+        .sum::<f64>() / (n - 1) as f64;
+    let standard_error = sample_variance.sqrt() / (n as f64).sqrt();
+
+    return Some(Duration::from_secs_f64(t_critical_value(n - 1) * standard_error));
+}
+
+/* Two-tailed 95% critical value of the t-distribution for `degrees_of_freedom`.
+
+   Tabulated up to 30, and stepped down towards the normal distribution's 1.96
+   after that - close enough at sizes where the difference barely shows. */
+fn t_critical_value(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228,
+        2.201, 2.179, 2.160, 2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086,
+        2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+    ];
+    return match degrees_of_freedom {
+        0 => f64::NAN,
+        1..=30 => TABLE[degrees_of_freedom - 1],
+        31..=40 => 2.021,
+        41..=60 => 2.000,
+        61..=120 => 1.980,
+        _ => 1.960,
+    };
+}
+
+/* Nearest-rank percentile, `p` being out of 100 */
+pub fn percentile(sorted_durations: &[Duration], p: f64) -> Option<Duration> {
+    if sorted_durations.is_empty() {
+        return None;
+    }
+    let rank = (p / 100_f64 * sorted_durations.len() as f64).ceil() as usize;
+
+    return Some(sorted_durations[rank.clamp(1, sorted_durations.len()) - 1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mean_of_many_multi_second_durations_is_exact() {
+        let durations = vec![Duration::from_secs(30); 100_000];
+        assert_eq!(mean(&durations), Some(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn mean_of_durations_whose_sum_would_overflow_a_duration() {
+        // summed as Durations, these would panic adding the third.
+        let huge = Duration::from_secs(u64::MAX / 2);
+        assert_eq!(mean(&[huge; 4]), Some(huge));
+    }
+
+    #[test]
+    fn mean_of_nothing_is_none() {
+        assert_eq!(mean(&[]), None);
+    }
+}
@@ -0,0 +1,47 @@
+use std::io;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use crate::statistics::{milliseconds, Statistics};
+
+/* Sends the headline figures for a run to a StatsD (or DogStatsD) collector.
+
+   Everything goes out as gauges in a single UDP packet, tagged DogStatsD-style
+   with the target's host. UDP means we never learn whether anyone was
+   listening, and nothing here is worth failing a run over, so any error
+   we do see is only a warning. */
+pub fn emit(collector: &str, statistics: &Statistics, host: &str) {
+    match send(collector, &packet(statistics, host)) {
+        Ok(()) => debug!("Sent metrics for {} to StatsD at {}", statistics.url, collector),
+        Err(e) => warn!("Could not send metrics to StatsD at {}: {}", collector, e),
+    }
+}
+
+fn packet(statistics: &Statistics, host: &str) -> String {
+    let gauge = |name: &str, value: f64| format!("http_profiler.{}:{}|g|#host:{}", name, value, host);
+
+    let mut lines = vec![
+        gauge("requests", statistics.total_requests as f64),
+        gauge("success_rate", statistics.percentage_succeeded),
+    ];
+    // with no successful responses there's no latency to speak of, and a 0
+    // would look like a very fast run on a dashboard.
+    let latencies: [(&str, Option<Duration>); 2] = [("latency.mean_ms", statistics.mean), ("latency.p99_ms", statistics.p99)];
+    for (name, latency) in latencies.iter() {
+        if let Some(latency) = latency {
+            lines.push(gauge(name, milliseconds(*latency)));
+        }
+    }
+
+    return lines.join("\n");
+}
+
+fn send(collector: &str, packet: &str) -> io::Result<()> {
+    let address = collector.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "the address did not resolve")
+    })?;
+    let socket = UdpSocket::bind(if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+    socket.send_to(packet.as_bytes(), address)?;
+
+    return Ok(());
+}
@@ -0,0 +1,103 @@
+use std::time::Duration;
+
+use crate::connect::TimeUnit;
+use crate::statistics::Statistics;
+
+const PLACEHOLDERS: [&str; 9] = ["url", "count", "success_rate", "failed", "min", "mean", "median", "p99", "max"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Placeholder(&'static str),
+}
+
+/* A `--template` format string, along the lines of curl's `-w`, e.g.
+   `{url} p99={p99}ms ok={success_rate}%\n`.
+
+   Placeholders are checked when the template is parsed, so a typo is an
+   error up front and not a blank in the output after a long run. `{{` and
+   `}}` stand for literal braces, and `\n` and `\t` for a newline and a tab. */
+#[derive(Debug, Clone)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(raw: &str) -> Result<Template, String> {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = raw.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    text.push(c);
+                }
+                ('\\', Some('n')) => {
+                    chars.next();
+                    text.push('\n');
+                }
+                ('\\', Some('t')) => {
+                    chars.next();
+                    text.push('\t');
+                }
+                ('{', _) => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed placeholder {{{} - write {{{{ for a literal brace", name)),
+                        }
+                    }
+                    let placeholder = PLACEHOLDERS.iter().find(|&&known| known == name).ok_or_else(|| {
+                        format!("unknown placeholder {{{}}} - the known ones are {{{}}}", name, PLACEHOLDERS.join("}, {"))
+                    })?;
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(text.split_off(0)));
+                    }
+                    pieces.push(Piece::Placeholder(placeholder));
+                }
+                ('}', _) => return Err(String::from("unmatched } - write }} for a literal brace")),
+                _ => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+
+        return Ok(Template{ pieces });
+    }
+
+    /* Fills the template in from `statistics`. Latencies are bare numbers in
+       `unit` (milliseconds if there isn't one), so they're easy to script
+       against, and anything there was nothing to measure for is a `-`. */
+    pub fn render(&self, statistics: &Statistics, unit: Option<TimeUnit>) -> String {
+        let latency = |duration: Option<Duration>| match duration {
+            Some(duration) => {
+                let seconds = duration.as_secs_f64();
+                match unit {
+                    Some(TimeUnit::Seconds) => format!("{:.3}", seconds),
+                    Some(TimeUnit::Microseconds) => format!("{:.3}", seconds * 1e6),
+                    Some(TimeUnit::Milliseconds) | None => format!("{:.3}", seconds * 1e3),
+                }
+            }
+            None => String::from("-"),
+        };
+
+        return self.pieces.iter().map(|piece| match piece {
+            Piece::Text(text) => text.clone(),
+            Piece::Placeholder("url") => statistics.url.clone(),
+            Piece::Placeholder("count") => statistics.total_requests.to_string(),
+            Piece::Placeholder("success_rate") => format!("{:.2}", statistics.percentage_succeeded),
+            Piece::Placeholder("failed") => statistics.failed_requests.to_string(),
+            Piece::Placeholder("min") => latency(statistics.fastest),
+            Piece::Placeholder("mean") => latency(statistics.mean),
+            Piece::Placeholder("median") => latency(statistics.median),
+            Piece::Placeholder("p99") => latency(statistics.p99),
+            Piece::Placeholder("max") => latency(statistics.slowest),
+            // parse only ever lets through the placeholders above.
+            Piece::Placeholder(other) => unreachable!("unknown placeholder {}", other),
+        }).collect();
+    }
+}
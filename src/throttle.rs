@@ -0,0 +1,79 @@
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::thread;
+use std::time::Duration;
+
+use crate::connect::{HalfClose, PeerAddress, ReadDeadline};
+
+/* A connection that moves no more than `bytes_per_second` each way, to
+   profile a server as a client on a slow link would see it.
+
+   Reads and writes are broken up into small pieces, each followed by however
+   long that many bytes would take at the rate - so data trickles, rather than
+   arriving in bursts with pauses between. Without a rate, it's just the
+   connection it wraps. */
+#[derive(Debug)]
+pub struct Throttled<S> {
+    inner: S,
+    bytes_per_second: Option<u64>,
+}
+
+impl<S> Throttled<S> {
+    pub fn new(inner: S, bytes_per_second: Option<u64>) -> Throttled<S> {
+        return Throttled{ inner, bytes_per_second };
+    }
+
+    /* How much to move at once: a tenth of a second's worth */
+    fn piece(&self, wanted: usize) -> usize {
+        return match self.bytes_per_second {
+            Some(rate) => wanted.min((rate / 10).max(1) as usize),
+            None => wanted,
+        };
+    }
+
+    fn pace(&self, bytes: usize) {
+        if let Some(rate) = self.bytes_per_second {
+            thread::sleep(Duration::from_secs_f64(bytes as f64 / rate as f64));
+        }
+    }
+}
+
+impl<S: Read> Read for Throttled<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let piece = self.piece(buf.len());
+        let bytes_read = self.inner.read(&mut buf[..piece])?;
+        self.pace(bytes_read);
+        return Ok(bytes_read);
+    }
+}
+
+impl<S: Write> Write for Throttled<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let piece = self.piece(buf.len());
+        let bytes_written = self.inner.write(&buf[..piece])?;
+        self.pace(bytes_written);
+        return Ok(bytes_written);
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        return self.inner.flush();
+    }
+}
+
+impl<S: ReadDeadline> ReadDeadline for Throttled<S> {
+    fn set_read_wait(&self, wait: Duration) -> io::Result<()> {
+        return self.inner.set_read_wait(wait);
+    }
+}
+
+impl<S: HalfClose> HalfClose for Throttled<S> {
+    fn shutdown_write(&self) -> io::Result<()> {
+        return self.inner.shutdown_write();
+    }
+}
+
+impl<S: PeerAddress> PeerAddress for Throttled<S> {
+    fn peer_address(&self) -> io::Result<SocketAddr> {
+        return self.inner.peer_address();
+    }
+}